@@ -0,0 +1,75 @@
+// Cost of handing the chunk map to the mesher: the old deep copy of every chunk versus the
+// shared Arc<Chunk> map. Run with `cargo bench --bench chunk_handoff`.
+//
+// The deep copy is measured twice: on real terrain, where palettes keep most chunks small, and
+// on chunks that all use flat storage, which is what every chunk cost before palettes.
+
+use std::{collections::HashMap, hint::black_box, mem::size_of, sync::Arc, time::Instant};
+
+use snake::{block::BlockType, chunk::Chunk, terrain::{HeightmapGenerator, TerrainGenerator, WorldSeed}};
+use strum::IntoEnumIterator;
+
+// a hand-off happens once per loaded chunk, time a few at the full map size
+fn time_clone<T: Clone>(map: &T) -> f64 {
+    let samples = 5;
+    let start = Instant::now();
+    for _ in 0..samples {
+        black_box(map.clone());
+    }
+    start.elapsed().as_secs_f64() / samples as f64
+}
+
+fn main() {
+    let generator = HeightmapGenerator::new(WorldSeed(42));
+    let block_types: Vec<BlockType> = BlockType::iter().collect();
+
+    for render_distance in [3, 5, 8] {
+        let mut terrain: HashMap<[i32; 3], Chunk> = HashMap::new();
+        let mut flat: HashMap<[i32; 3], Chunk> = HashMap::new();
+        let mut shared: HashMap<[i32; 3], Arc<Chunk>> = HashMap::new();
+        for x in -render_distance..=render_distance {
+            // centred on the surface, which sits around chunk y 1
+            for y in 1 - render_distance..=1 + render_distance {
+                for z in -render_distance..=render_distance {
+                    let chunk = generator.generate([x, y, z]);
+                    terrain.insert(chunk.origin, chunk.clone());
+                    shared.insert(chunk.origin, Arc::new(chunk));
+                    // more block types than a palette holds
+                    flat.insert([x, y, z], Chunk::from_fn([x, y, z], |x, y, z| block_types[(x + y + z) % block_types.len()]));
+                }
+            }
+        }
+        let chunks = terrain.len();
+
+        let deep = time_clone(&terrain);
+        let deep_flat = time_clone(&flat);
+        let arc = time_clone(&shared);
+
+        // what each hand-off copies, ignoring the hash table itself which all of them pay
+        let deep_bytes: usize = terrain.values().map(|chunk| chunk.memory_usage()).sum();
+        let flat_bytes: usize = flat.values().map(|chunk| chunk.memory_usage()).sum();
+        let arc_bytes = chunks * size_of::<Arc<Chunk>>();
+
+        println!("render distance {} ({} chunks):", render_distance, chunks);
+        println!(
+            "  deep copy, flat chunks: {:.3} ms and {:.1} MiB per hand-off, ~{:.1} s for a full load",
+            deep_flat * 1000.0,
+            flat_bytes as f64 / (1024.0 * 1024.0),
+            deep_flat * chunks as f64,
+        );
+        println!(
+            "  deep copy, terrain: {:.3} ms and {:.1} MiB per hand-off, ~{:.1} s for a full load",
+            deep * 1000.0,
+            deep_bytes as f64 / (1024.0 * 1024.0),
+            deep * chunks as f64,
+        );
+        println!(
+            "  Arc<Chunk>: {:.3} ms and {:.1} KiB per hand-off, ~{:.3} s for a full load ({:.0}x faster than flat, {:.0}x than terrain)",
+            arc * 1000.0,
+            arc_bytes as f64 / 1024.0,
+            arc * chunks as f64,
+            deep_flat / arc,
+            deep / arc,
+        );
+    }
+}
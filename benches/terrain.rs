@@ -0,0 +1,74 @@
+// Chunk generation throughput with and without the shared column cache.
+// Run with `cargo bench --bench terrain`.
+
+use std::{sync::{mpsc::channel, Arc}, time::Instant};
+
+use snake::terrain::{HeightmapGenerator, TerrainGenerator, WorldSeed};
+use threadpool::ThreadPool;
+
+// same cube of chunks the game loads around the player
+fn origins(render_distance: i32) -> Vec<[i32; 3]> {
+    let mut origins = Vec::new();
+    for x in -render_distance..=render_distance {
+        for y in -render_distance..=render_distance {
+            for z in -render_distance..=render_distance {
+                origins.push([x, y, z]);
+            }
+        }
+    }
+    origins
+}
+
+// generates every origin on a pool the size the game uses, returns chunks per second
+fn run(generator: Arc<HeightmapGenerator>, origins: &[[i32; 3]]) -> f64 {
+    let pool = ThreadPool::new(8);
+    let (tx, rx) = channel();
+
+    let start = Instant::now();
+    for &origin in origins {
+        let tx = tx.clone();
+        let generator = generator.clone();
+        pool.execute(move || {
+            let mut chunk = generator.generate(origin);
+            generator.decorate(&mut chunk);
+            tx.send(chunk.origin).unwrap();
+        });
+    }
+    drop(tx);
+    let generated = rx.iter().count();
+
+    generated as f64 / start.elapsed().as_secs_f64()
+}
+
+fn main() {
+    let seed = WorldSeed(1234);
+    let rounds = 3;
+
+    for render_distance in [3, 5] {
+        let origins = origins(render_distance);
+
+        let mut uncached = 0.0;
+        let mut cached = 0.0;
+        for round in 0..rounds {
+            // offset every round so each run starts on fresh columns
+            let shifted: Vec<[i32; 3]> = origins.iter()
+                .map(|o| [o[0] + round * 100, o[1], o[2]])
+                .collect();
+
+            let mut generator = HeightmapGenerator::new(seed);
+            generator.column_cache = None;
+            uncached += run(Arc::new(generator), &shifted);
+
+            cached += run(Arc::new(HeightmapGenerator::new(seed)), &shifted);
+        }
+
+        println!(
+            "render distance {} ({} chunks): {:.0} chunks/s without column cache, {:.0} chunks/s with ({:.2}x)",
+            render_distance,
+            origins.len(),
+            uncached / rounds as f64,
+            cached / rounds as f64,
+            cached / uncached,
+        );
+    }
+}
@@ -0,0 +1,281 @@
+use std::{collections::BTreeMap, io::{self, Read}};
+
+use flate2::read::{GzDecoder, ZlibDecoder};
+
+use crate::{block::BlockType, chunk::Chunk, nbt::{self, Tag}};
+
+const SECTOR_SIZE: usize = 4096;
+// 32x32 chunk columns per region file
+const REGION_COLUMNS: usize = 1024;
+// first data version where packed block indices no longer straddle two longs (1.16)
+const NON_SPANNING_DATA_VERSION: i64 = 2529;
+
+/// Chunks read out of an Anvil region file, plus the block names we had no type for and the
+/// chunk columns we couldn't read at all.
+#[derive(Default)]
+pub struct AnvilImport {
+    pub chunks: Vec<Chunk>,
+    // block name -> how many sections it showed up in, those blocks became the placeholder
+    pub unknown_blocks: BTreeMap<String, usize>,
+    // [x, z] of the column within the region and why it was left out
+    pub skipped_columns: Vec<([usize; 2], String)>,
+}
+
+// minecraft's name for a block, with or without the namespace, to one of ours. None if we have nothing close
+// block states like "minecraft:oak_log[axis=y]" are fine, the properties are ignored
+pub fn block_from_name(name: &str) -> Option<BlockType> {
+    let name = name.split('[').next().unwrap_or(name);
+    let name = name.strip_prefix("minecraft:").unwrap_or(name);
+    let block_type = match name {
+        "air" | "cave_air" | "void_air" => BlockType::Air,
+        "stone" | "granite" | "diorite" | "andesite" | "deepslate" | "tuff" | "cobblestone" | "gravel" | "calcite" => BlockType::Stone,
+        "dirt" | "coarse_dirt" | "rooted_dirt" | "podzol" | "mycelium" | "farmland" | "dirt_path" | "mud" | "clay" => BlockType::Dirt,
+        "grass_block" => BlockType::Grass,
+        "sand" | "red_sand" | "sandstone" | "red_sandstone" => BlockType::Sand,
+        "snow" | "snow_block" | "powder_snow" | "ice" | "packed_ice" | "blue_ice" => BlockType::Snow,
+        "water" | "bubble_column" => BlockType::Water,
+        "bedrock" => BlockType::Bedrock,
+        "tnt" => BlockType::TNT,
+        "coal_ore" | "deepslate_coal_ore" => BlockType::CoalOre,
+        "iron_ore" | "deepslate_iron_ore" => BlockType::IronOre,
+        "gold_ore" | "deepslate_gold_ore" => BlockType::GoldOre,
+        "diamond_ore" | "deepslate_diamond_ore" => BlockType::DiamondOre,
+        "grass" | "short_grass" | "tall_grass" | "fern" | "large_fern" | "dead_bush" => BlockType::TallGrass,
+        "dandelion" | "poppy" | "blue_orchid" | "allium" | "azure_bluet" | "red_tulip" | "orange_tulip" | "white_tulip"
+        | "pink_tulip" | "oxeye_daisy" | "cornflower" | "lily_of_the_valley" | "sunflower" | "lilac" | "rose_bush"
+        | "peony" => BlockType::Flower,
+        _ if name.ends_with("_log") || name.ends_with("_wood") || name.ends_with("_stem") || name.ends_with("_hyphae") => BlockType::Log,
+        _ if name.ends_with("_leaves") => BlockType::Leaves,
+        _ => return None,
+    };
+    Some(block_type)
+}
+
+// the other way round, for writing files other tools can read
+pub fn block_name(block_type: BlockType) -> &'static str {
+    match block_type {
+        BlockType::Air => "minecraft:air",
+        BlockType::TNT => "minecraft:tnt",
+        BlockType::Grass => "minecraft:grass_block",
+        BlockType::Dirt => "minecraft:dirt",
+        BlockType::Stone => "minecraft:stone",
+        BlockType::Bedrock => "minecraft:bedrock",
+        BlockType::Sand => "minecraft:sand",
+        BlockType::Snow => "minecraft:snow_block",
+        BlockType::Water => "minecraft:water",
+        BlockType::CoalOre => "minecraft:coal_ore",
+        BlockType::IronOre => "minecraft:iron_ore",
+        BlockType::GoldOre => "minecraft:gold_ore",
+        BlockType::DiamondOre => "minecraft:diamond_ore",
+        BlockType::Log => "minecraft:oak_log",
+        BlockType::Leaves => "minecraft:oak_leaves",
+        BlockType::TallGrass => "minecraft:short_grass",
+        BlockType::Flower => "minecraft:dandelion",
+    }
+}
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("anvil: {}", msg.into()))
+}
+
+// every chunk in a region file. each 16x16x16 section becomes one of our chunks at
+// [section x, section y, section z], blocks we can't map become `placeholder`. columns with a
+// compression we can't read are skipped and listed, a broken file is still an error
+pub fn import_region(bytes: &[u8], placeholder: BlockType) -> io::Result<AnvilImport> {
+    if bytes.len() < 2 * SECTOR_SIZE {
+        return Err(invalid("region file is missing its header"));
+    }
+
+    let mut import = AnvilImport::default();
+    for column in 0..REGION_COLUMNS {
+        let entry = &bytes[column * 4..column * 4 + 4];
+        let sector = u32::from_be_bytes([0, entry[0], entry[1], entry[2]]) as usize;
+        if sector == 0 {
+            continue;
+        }
+
+        let start = sector * SECTOR_SIZE;
+        let header = bytes.get(start..start + 5).ok_or_else(|| invalid(format!("chunk {} starts past end of file", column)))?;
+        let length = u32::from_be_bytes(header[0..4].try_into().unwrap()) as usize;
+        // the length counts the compression byte
+        let data = bytes.get(start + 5..start + 4 + length).ok_or_else(|| invalid(format!("chunk {} runs past end of file", column)))?;
+
+        let mut nbt_bytes = Vec::new();
+        match header[4] {
+            1 => { GzDecoder::new(data).read_to_end(&mut nbt_bytes)?; }
+            2 => { ZlibDecoder::new(data).read_to_end(&mut nbt_bytes)?; }
+            3 => nbt_bytes.extend_from_slice(data),
+            // the high bit means the chunk was too big and lives in its own .mcc file next to the region
+            compression if compression & 0x80 != 0 => {
+                import.skipped_columns.push(([column % 32, column / 32], "stored in a separate .mcc file".to_string()));
+                continue;
+            }
+            // 4 is lz4, anything else is from a newer version than we know about
+            other => {
+                import.skipped_columns.push(([column % 32, column / 32], format!("unsupported compression {}", other)));
+                continue;
+            }
+        }
+
+        let (_, root) = nbt::read(&nbt_bytes)?;
+        import_column(&root, [column % 32, column / 32], placeholder, &mut import)?;
+    }
+    Ok(import)
+}
+
+// one chunk column, in either the 1.18+ layout or the older one nested under "Level". columns from
+// before 1.13 store numeric block ids instead of a palette and are skipped
+fn import_column(root: &Tag, position: [usize; 2], placeholder: BlockType, import: &mut AnvilImport) -> io::Result<()> {
+    let data_version = root.get("DataVersion").and_then(Tag::as_i64).unwrap_or(0);
+    let level = root.get("Level").unwrap_or(root);
+
+    let x = level.get("xPos").and_then(Tag::as_i32).ok_or_else(|| invalid("chunk without xPos"))?;
+    let z = level.get("zPos").and_then(Tag::as_i32).ok_or_else(|| invalid("chunk without zPos"))?;
+    let sections = level.get("sections").or_else(|| level.get("Sections")).and_then(Tag::as_list).unwrap_or_default();
+    if sections.iter().any(|section| section.get("Blocks").is_some() && section.get("Palette").is_none()) {
+        import.skipped_columns.push((position, "pre-1.13 section format unsupported".to_string()));
+        return Ok(());
+    }
+
+    for section in sections {
+        let Some(y) = section.get("Y").and_then(Tag::as_i32) else {
+            continue;
+        };
+        // 1.18+ keeps both under block_states, older versions have them directly on the section
+        let (palette, data) = match section.get("block_states") {
+            Some(states) => (states.get("palette"), states.get("data")),
+            None => (section.get("Palette"), section.get("BlockStates")),
+        };
+        // sections that are only there for lighting have no blocks
+        let Some(palette) = palette.and_then(Tag::as_list) else {
+            continue;
+        };
+
+        let palette: Vec<BlockType> = palette.iter().map(|entry| {
+            let name = entry.get("Name").and_then(Tag::as_str).unwrap_or("");
+            block_from_name(name).unwrap_or_else(|| {
+                *import.unknown_blocks.entry(name.to_string()).or_default() += 1;
+                placeholder
+            })
+        }).collect();
+
+        let indices = unpack_indices(data.and_then(Tag::as_long_array).unwrap_or_default(), palette.len(), data_version >= NON_SPANNING_DATA_VERSION)?;
+        let chunk = Chunk::from_fn([x, y, z], |bx, by, bz| {
+            // anvil orders blocks y, z, x
+            let index = indices.get(by * 256 + bz * 16 + bx).copied().unwrap_or(0);
+            palette.get(index).copied().unwrap_or(placeholder)
+        });
+        if !chunk.is_empty() {
+            import.chunks.push(chunk);
+        }
+    }
+    Ok(())
+}
+
+// palette index of each of the 4096 blocks. a single entry palette has no data and every index is 0
+fn unpack_indices(data: &[i64], palette_len: usize, non_spanning: bool) -> io::Result<Vec<usize>> {
+    if palette_len <= 1 {
+        return Ok(vec![0; 4096]);
+    }
+    let bits = (usize::BITS - (palette_len - 1).leading_zeros()).max(4) as usize;
+    let mask = (1u64 << bits) - 1;
+
+    let needed = if non_spanning { 4096_usize.div_ceil(64 / bits) } else { (4096 * bits).div_ceil(64) };
+    if data.len() < needed {
+        return Err(invalid(format!("section has {} longs of block data, expected {}", data.len(), needed)));
+    }
+
+    Ok((0..4096).map(|i| {
+        if non_spanning {
+            let per_long = 64 / bits;
+            ((data[i / per_long] as u64 >> ((i % per_long) * bits)) & mask) as usize
+        } else {
+            let bit = i * bits;
+            let (long, offset) = (bit / 64, bit % 64);
+            let mut value = data[long] as u64 >> offset;
+            if offset + bits > 64 {
+                value |= (data[long + 1] as u64) << (64 - offset);
+            }
+            (value & mask) as usize
+        }
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk_at(import: &AnvilImport, origin: [i32; 3]) -> &Chunk {
+        import.chunks.iter().find(|chunk| chunk.origin == origin).unwrap_or_else(|| panic!("no chunk at {:?}", origin))
+    }
+
+    #[test]
+    fn reads_every_compression_and_skips_what_it_cant() {
+        let import = import_region(include_bytes!("../tests/fixtures/anvil/modern.mca"), BlockType::Snow).unwrap();
+
+        // the all air section and the lighting only one don't make chunks
+        let mut origins: Vec<[i32; 3]> = import.chunks.iter().map(|chunk| chunk.origin).collect();
+        origins.sort();
+        assert_eq!(origins, vec![[0, -1, 0], [0, 2, 1], [1, 0, 0]]);
+
+        // zlib, 4 bit indices
+        let zlib = chunk_at(&import, [0, -1, 0]);
+        assert_eq!(zlib.get(5, 0, 9), BlockType::Stone);
+        assert_eq!(zlib.get(7, 5, 7), BlockType::Log);
+        assert_eq!(zlib.get(7, 5, 8), BlockType::Air);
+        assert_eq!(zlib.get(1, 2, 3), BlockType::Snow);
+        assert_eq!(import.unknown_blocks, BTreeMap::from([("minecraft:amethyst_block".to_string(), 1)]));
+
+        // gzip, a single entry palette without data
+        let gzip = chunk_at(&import, [1, 0, 0]);
+        assert!((0..16).all(|y| gzip.get(3, y, 12) == BlockType::Stone));
+
+        // uncompressed
+        let raw = chunk_at(&import, [0, 2, 1]);
+        assert_eq!(raw.get(4, 3, 4), BlockType::Sand);
+        assert_eq!(raw.get(4, 4, 4), BlockType::Air);
+
+        assert_eq!(import.skipped_columns, vec![
+            ([2, 0], "unsupported compression 4".to_string()),
+            ([3, 0], "stored in a separate .mcc file".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn reads_pre_1_16_indices_that_span_longs() {
+        let import = import_region(include_bytes!("../tests/fixtures/anvil/legacy.mca"), BlockType::Snow).unwrap();
+        assert_eq!(import.chunks.len(), 1);
+        assert!(import.unknown_blocks.is_empty());
+        assert!(import.skipped_columns.is_empty());
+
+        let names = ["air", "stone", "granite", "dirt", "grass_block", "sand", "bedrock", "coal_ore", "iron_ore", "gold_ore",
+            "diamond_ore", "water", "tnt", "oak_leaves", "spruce_log", "snow_block", "poppy"];
+        let chunk = chunk_at(&import, [-1, 3, -1]);
+        for y in 0..16 {
+            for z in 0..16 {
+                for x in 0..16 {
+                    let expected = block_from_name(names[(x + 2 * y + 3 * z) % 17]).unwrap();
+                    assert_eq!(chunk.get(x, y, z), expected, "block {} {} {}", x, y, z);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn skips_pre_1_13_columns_and_keeps_the_rest() {
+        let import = import_region(include_bytes!("../tests/fixtures/anvil/pre_flattening.mca"), BlockType::Snow).unwrap();
+        assert_eq!(import.skipped_columns, vec![([0, 0], "pre-1.13 section format unsupported".to_string())]);
+
+        assert_eq!(import.chunks.len(), 1);
+        let chunk = chunk_at(&import, [1, 0, 0]);
+        assert_eq!(chunk.get(9, 7, 2), BlockType::Stone);
+        assert_eq!(chunk.get(9, 8, 2), BlockType::Air);
+    }
+
+    #[test]
+    fn truncated_file_is_an_error() {
+        let bytes = include_bytes!("../tests/fixtures/anvil/modern.mca");
+        assert!(import_region(&bytes[..3 * SECTOR_SIZE], BlockType::Stone).is_err());
+        assert!(import_region(&bytes[..100], BlockType::Stone).is_err());
+    }
+}
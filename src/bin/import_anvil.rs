@@ -0,0 +1,86 @@
+// Imports Minecraft Anvil region files into a world directory.
+//
+// usage: import_anvil <world dir> <region.mca>...
+//
+// Every section of every chunk in the region files is written to the world's region files.
+// Blocks with no matching type become stone and are listed at the end, so are chunks that
+// couldn't be read (external .mcc files, lz4 compression, worlds from before 1.13). A world
+// without a level file gets one using the void generator, so nothing is generated around the
+// import.
+
+use std::{collections::BTreeMap, fs, process};
+
+use snake::{anvil::import_region, block::BlockType, chunk::Chunk, world_save::{LevelData, WorldSave}};
+
+fn usage() -> ! {
+    eprintln!("usage: import_anvil <world dir> <region.mca>...");
+    process::exit(1);
+}
+
+// above the highest block in the middle column of the topmost chunk there
+fn spawn_above(chunks: &[Chunk]) -> [f32; 3] {
+    let Some(top) = chunks.iter().max_by_key(|chunk| chunk.origin[1]) else {
+        return [0.0, 30.0, 0.0];
+    };
+    let columns = chunks.iter().filter(|chunk| chunk.origin[0] == top.origin[0] && chunk.origin[2] == top.origin[2]);
+    let height = columns
+        .flat_map(|chunk| (0..16).filter(|y| chunk.get(8, *y, 8) != BlockType::Air).map(|y| chunk.origin[1] * 16 + y as i32))
+        .max()
+        .unwrap_or(top.origin[1] * 16);
+    [(top.origin[0] * 16 + 8) as f32, height as f32 + 2.0, (top.origin[2] * 16 + 8) as f32]
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 3 {
+        usage();
+    }
+
+    let save = WorldSave::open(&args[1]).unwrap_or_else(|e| {
+        eprintln!("Failed to open world directory {}: {}", args[1], e);
+        process::exit(1);
+    });
+
+    let mut unknown_blocks: BTreeMap<String, usize> = BTreeMap::new();
+    let mut imported = Vec::new();
+    for path in &args[2..] {
+        let import = fs::read(path).and_then(|bytes| import_region(&bytes, BlockType::Stone));
+        match import {
+            Ok(import) => {
+                println!("{}: {} chunks", path, import.chunks.len());
+                for ([x, z], reason) in &import.skipped_columns {
+                    println!("  skipped column {} {}: {}", x, z, reason);
+                }
+                for (name, count) in import.unknown_blocks {
+                    *unknown_blocks.entry(name).or_default() += count;
+                }
+                if let Err(e) = save.save_chunks(&import.chunks) {
+                    eprintln!("Failed to save chunks from {}: {}", path, e);
+                    process::exit(1);
+                }
+                imported.extend(import.chunks);
+            }
+            Err(e) => eprintln!("Skipping {}: {}", path, e),
+        }
+    }
+
+    match save.load_level() {
+        Ok(Some(_)) => (),
+        Ok(None) => {
+            let level = LevelData { seed: 0, generator: "void".to_string(), spawn: spawn_above(&imported), player: None };
+            if let Err(e) = save.save_level(&level) {
+                eprintln!("Failed to write level file: {}", e);
+                process::exit(1);
+            }
+        }
+        Err(e) => eprintln!("Existing level file is unreadable, left as is: {}", e),
+    }
+
+    println!("Imported {} chunks into {}", imported.len(), args[1]);
+    if !unknown_blocks.is_empty() {
+        println!("Unmapped blocks (placed as stone):");
+        for (name, count) in unknown_blocks {
+            println!("  {} (in {} sections)", name, count);
+        }
+    }
+}
@@ -0,0 +1,150 @@
+// Headless world generation, no window needed.
+//
+// usage: worldgen <seed> <min x,y,z> <max x,y,z> <output dir> [generator]
+//
+// Generates every chunk between the two chunk coordinates (inclusive), writes them to
+// <output dir>/chunks.bin, a top-down preview to <output dir>/map.png and prints a summary
+// that is also saved to <output dir>/summary.txt.
+
+use std::{collections::{HashMap, HashSet}, fmt::Write as _, fs, path::PathBuf, process, sync::{mpsc::channel, Arc, Mutex}, time::Instant};
+
+use snake::{block::BlockType, chunk::Chunk, chunk_manager::ChunkManager, map::render_map, terrain::{generator_from_name, WorldSeed}};
+use strum::IntoEnumIterator;
+use threadpool::ThreadPool;
+
+fn parse_coords(arg: &str) -> Option<[i32; 3]> {
+    let parts: Vec<i32> = arg.split(',').map(|part| part.trim().parse().ok()).collect::<Option<_>>()?;
+    parts.try_into().ok()
+}
+
+fn usage() -> ! {
+    eprintln!("usage: worldgen <seed> <min x,y,z> <max x,y,z> <output dir> [generator]");
+    process::exit(1);
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 5 {
+        usage();
+    }
+
+    let seed = WorldSeed::from_arg(&args[1]);
+    let (Some(min), Some(max)) = (parse_coords(&args[2]), parse_coords(&args[3])) else {
+        usage();
+    };
+    if (0..3).any(|axis| min[axis] > max[axis]) {
+        eprintln!("min {:?} is above max {:?}", min, max);
+        usage();
+    }
+    let out_dir = PathBuf::from(&args[4]);
+    let generator_name = args.get(5).map(String::as_str).unwrap_or("default");
+    let Some(generator) = generator_from_name(generator_name, seed) else {
+        eprintln!("Unknown terrain generator '{}'", generator_name);
+        process::exit(1);
+    };
+
+    let mut origins = Vec::new();
+    for x in min[0]..=max[0] {
+        for y in min[1]..=max[1] {
+            for z in min[2]..=max[2] {
+                origins.push([x, y, z]);
+            }
+        }
+    }
+    println!("Generating {} chunks with seed {}", origins.len(), seed.0);
+
+    let start = Instant::now();
+    let pool = ThreadPool::new(8);
+    let (tx, rx) = channel();
+    for &origin in &origins {
+        let tx = tx.clone();
+        let generator = generator.clone();
+        pool.execute(move || {
+            let mut chunk = generator.generate(origin);
+            let spill = generator.decorate(&mut chunk);
+            tx.send((chunk, spill)).expect("Failed to send chunk");
+        });
+    }
+    drop(tx);
+
+    // same hand-off as the game so trees crossing chunk borders come out whole
+    let mut chunks: HashMap<[i32; 3], Arc<Chunk>> = HashMap::new();
+    let pending_edits = Mutex::new(HashMap::new());
+    let modified = Mutex::new(HashSet::new());
+    for (mut chunk, spill) in rx {
+        ChunkManager::place_features(&mut chunk, spill, &mut chunks, &pending_edits, &modified);
+        chunks.insert(chunk.origin, Arc::new(chunk));
+    }
+    let elapsed = start.elapsed();
+
+    if let Err(e) = fs::create_dir_all(&out_dir) {
+        eprintln!("Failed to create {}: {}", out_dir.display(), e);
+        process::exit(1);
+    }
+
+    // chunk count followed by every chunk, sorted so the same seed gives the same file
+    let mut sorted: Vec<&Arc<Chunk>> = chunks.values().collect();
+    sorted.sort_by_key(|chunk| chunk.origin);
+    let mut bytes = (sorted.len() as u32).to_le_bytes().to_vec();
+    for chunk in &sorted {
+        bytes.extend(chunk.to_bytes());
+    }
+    fs::write(out_dir.join("chunks.bin"), bytes).expect("Failed to write chunks");
+
+    render_map(&chunks, [min[0], min[2]], [max[0], max[2]])
+        .save(out_dir.join("map.png"))
+        .expect("Failed to write map");
+
+    let summary = summarize(&chunks, elapsed.as_secs_f64());
+    print!("{}", summary);
+    fs::write(out_dir.join("summary.txt"), summary).expect("Failed to write summary");
+}
+
+fn summarize(chunks: &HashMap<[i32; 3], Arc<Chunk>>, seconds: f64) -> String {
+    let mut counts: HashMap<BlockType, u64> = HashMap::new();
+    // highest non-air block per world column
+    let mut heights: HashMap<[i32; 2], i32> = HashMap::new();
+
+    for chunk in chunks.values() {
+        for x in 0..16 {
+            for y in 0..16 {
+                for z in 0..16 {
+                    let block_type = chunk.get(x, y, z);
+                    *counts.entry(block_type).or_default() += 1;
+
+                    if block_type != BlockType::Air {
+                        let column = [chunk.origin[0] * 16 + x as i32, chunk.origin[2] * 16 + z as i32];
+                        let world_y = chunk.origin[1] * 16 + y as i32;
+                        let height = heights.entry(column).or_insert(world_y);
+                        *height = (*height).max(world_y);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut summary = String::new();
+    let _ = writeln!(summary, "{} chunks in {:.2}s ({:.0} chunks/s)", chunks.len(), seconds, chunks.len() as f64 / seconds);
+
+    let memory: usize = chunks.values().map(|chunk| chunk.memory_usage()).sum();
+    let per_chunk = if chunks.is_empty() { 0.0 } else { memory as f64 / chunks.len() as f64 };
+    let _ = writeln!(summary, "Chunk memory: {:.1} KiB ({:.0} bytes per chunk)", memory as f64 / 1024.0, per_chunk);
+
+    let _ = writeln!(summary, "Block counts:");
+    for block_type in BlockType::iter() {
+        if let Some(count) = counts.get(&block_type) {
+            let _ = writeln!(summary, "  {:?}: {}", block_type, count);
+        }
+    }
+
+    if heights.is_empty() {
+        let _ = writeln!(summary, "Heights: no solid blocks");
+    } else {
+        let min = heights.values().min().unwrap();
+        let max = heights.values().max().unwrap();
+        let mean = heights.values().map(|&h| h as f64).sum::<f64>() / heights.len() as f64;
+        let _ = writeln!(summary, "Heights over {} columns: min {}, max {}, mean {:.1}", heights.len(), min, max, mean);
+    }
+
+    summary
+}
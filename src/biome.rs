@@ -0,0 +1,145 @@
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+
+use crate::{block::BlockType, terrain::WorldSeed};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumIter)]
+pub enum Biome {
+    Plains,
+    Hills,
+    Desert,
+    Snowy,
+    Ocean,
+}
+
+/// How a biome shapes the terrain and what it is covered with.
+pub struct BiomeParams {
+    // height = base_height + height_scale * noise, noise is roughly in [-1, 1]
+    pub base_height: f64,
+    pub height_scale: f64,
+    // horizontal stretch of the terrain noise, bigger is smoother
+    pub horizontal_scale: f64,
+    pub surface: BlockType,
+    pub filler: BlockType,
+    // where the biome sits in (temperature, humidity) space, both in [-1, 1]
+    pub climate: [f64; 2],
+}
+
+impl Biome {
+    pub fn params(&self) -> BiomeParams {
+        match self {
+            // plains keep the original heightmap (0 - 60 blocks, scale 150)
+            Biome::Plains => BiomeParams {
+                base_height: 30.0, height_scale: 30.0, horizontal_scale: 150.0,
+                surface: BlockType::Grass, filler: BlockType::Dirt,
+                climate: [0.3, 0.2],
+            },
+            Biome::Hills => BiomeParams {
+                base_height: 40.0, height_scale: 45.0, horizontal_scale: 110.0,
+                surface: BlockType::Grass, filler: BlockType::Dirt,
+                climate: [0.0, -0.4],
+            },
+            Biome::Desert => BiomeParams {
+                base_height: 28.0, height_scale: 10.0, horizontal_scale: 200.0,
+                surface: BlockType::Sand, filler: BlockType::Sand,
+                climate: [0.8, -0.6],
+            },
+            Biome::Snowy => BiomeParams {
+                base_height: 34.0, height_scale: 30.0, horizontal_scale: 150.0,
+                surface: BlockType::Snow, filler: BlockType::Dirt,
+                climate: [-0.8, 0.0],
+            },
+            Biome::Ocean => BiomeParams {
+                base_height: 4.0, height_scale: 10.0, horizontal_scale: 200.0,
+                surface: BlockType::Sand, filler: BlockType::Sand,
+                climate: [0.1, 0.8],
+            },
+        }
+    }
+}
+
+/// What the generator needs to know about a single x, z column.
+#[derive(Clone, Copy, Debug)]
+pub struct ColumnSample {
+    pub height: i32,
+    pub biome: Biome,
+}
+
+/// Samples biomes from temperature/humidity noise and blends their terrain per column.
+pub struct BiomeMap {
+    temperature: Fbm<Perlin>,
+    humidity: Fbm<Perlin>,
+    terrain: Fbm<Perlin>,
+}
+
+impl BiomeMap {
+    // how far (in blocks) one climate noise cycle stretches
+    const CLIMATE_SCALE: f64 = 600.0;
+    // width of the blend between biomes in climate space, smaller means sharper borders
+    const BLEND_WIDTH: f64 = 0.25;
+
+    pub fn new(seed: WorldSeed) -> Self {
+        let terrain = Fbm::<Perlin>::new(seed.0)
+            .set_octaves(4)
+            .set_persistence(0.6)
+            .set_frequency(0.7)
+            .set_lacunarity(2.2);
+        let temperature = Fbm::<Perlin>::new(seed.0.wrapping_add(10)).set_octaves(2);
+        let humidity = Fbm::<Perlin>::new(seed.0.wrapping_add(11)).set_octaves(2);
+
+        BiomeMap { temperature, humidity, terrain }
+    }
+
+    // (temperature, humidity) at a world column, both roughly in [-1, 1]
+    pub fn climate(&self, world_x: i32, world_z: i32) -> [f64; 2] {
+        let point = [world_x as f64 / Self::CLIMATE_SCALE, world_z as f64 / Self::CLIMATE_SCALE];
+        [self.temperature.get(point), self.humidity.get(point)]
+    }
+
+    // normalised weight of every biome for a climate. the weights change smoothly with the
+    // climate, so blended heights never jump at a biome border
+    pub fn weights(climate: [f64; 2]) -> Vec<(Biome, f64)> {
+        let mut weights: Vec<(Biome, f64)> = Biome::iter()
+            .map(|biome| {
+                let center = biome.params().climate;
+                let distance_sq = (climate[0] - center[0]).powi(2) + (climate[1] - center[1]).powi(2);
+                (biome, (-distance_sq / Self::BLEND_WIDTH.powi(2)).exp())
+            })
+            .collect();
+
+        let total: f64 = weights.iter().map(|(_, weight)| weight).sum();
+        for (_, weight) in weights.iter_mut() {
+            *weight /= total;
+        }
+        weights
+    }
+
+    pub fn sample_column(&self, world_x: i32, world_z: i32) -> ColumnSample {
+        let weights = Self::weights(self.climate(world_x, world_z));
+
+        let mut height = 0.0;
+        let mut biome = Biome::Plains;
+        let mut best_weight = 0.0;
+
+        for (candidate, weight) in weights {
+            if weight > best_weight {
+                best_weight = weight;
+                biome = candidate;
+            }
+            // far away biomes barely contribute, skip their noise lookup
+            if weight < 0.001 {
+                continue;
+            }
+
+            let params = candidate.params();
+            let noise_value = self.terrain.get([
+                world_x as f64 / params.horizontal_scale,
+                world_z as f64 / params.horizontal_scale,
+            ]);
+            height += weight * (params.base_height + params.height_scale * noise_value);
+        }
+
+        ColumnSample { height: height as i32, biome }
+    }
+}
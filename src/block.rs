@@ -11,6 +11,16 @@ pub struct Vertex {
 }
 implement_vertex!(Vertex, position, tex_coords, normal);
 
+impl Vertex {
+    pub fn position(&self) -> [f32; 3] {
+        self.position
+    }
+
+    pub fn normal(&self) -> [f32; 3] {
+        self.normal
+    }
+}
+
 #[derive(Debug, EnumIter, PartialEq)]
 pub enum FaceDir {
     Up,
@@ -26,7 +36,7 @@ impl FaceDir {
     // normal_axis is the axis perpendicular to the face
     // u_axis is the horizontal texture axis
     // v_axis is the vertical texture axis
-    fn get_axes(&self) -> (Vec3, Vec3, Vec3) {
+    pub fn get_axes(&self) -> (Vec3, Vec3, Vec3) {
         match self {
             FaceDir::Up => (
                 Vec3::new(0.0, 1.0, 0.0),  // Normal points up
@@ -68,12 +78,51 @@ pub struct Block {
     pub pos: [f32; 3],
 } 
 
-#[derive(PartialEq, Debug, Clone, Copy)]
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, EnumIter)]
 pub enum BlockType {
     Air,
     TNT,
     Grass,
     Dirt,
+    Stone,
+    Bedrock,
+    Sand,
+    Snow,
+    Water,
+    CoalOre,
+    IronOre,
+    GoldOre,
+    DiamondOre,
+    Log,
+    Leaves,
+    TallGrass,
+    Flower,
+}
+
+impl BlockType {
+    // stable id used when writing chunks to disk, new types must only be added at the end
+    pub fn id(&self) -> u8 {
+        *self as u8
+    }
+
+    pub fn from_id(id: u8) -> Option<BlockType> {
+        BlockType::iter().find(|block_type| block_type.id() == id)
+    }
+
+    // opaque blocks hide the faces of whatever is next to them
+    pub fn is_opaque(&self) -> bool {
+        !matches!(self, BlockType::Air | BlockType::Water | BlockType::TallGrass | BlockType::Flower)
+    }
+
+    // plants are drawn as two crossed quads instead of a cube
+    pub fn is_plant(&self) -> bool {
+        matches!(self, BlockType::TallGrass | BlockType::Flower)
+    }
+
+    // see-through blocks that still get drawn, these go in the translucent mesh
+    pub fn is_translucent(&self) -> bool {
+        matches!(self, BlockType::Water)
+    }
 }
 
 impl Block {
@@ -99,28 +148,77 @@ impl Block {
         (vertices, indices)
     }
 
+    // two quads crossing diagonally through the block, used for plants
+    pub fn generate_cross(&self) -> (Vec<Vertex>, Vec<u32>) {
+        let base_pos = Vec3::from(self.pos);
+        let mut vertices: Vec<Vertex> = Vec::new();
+        let mut indices: Vec<u32> = Vec::new();
+
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let diagonals = [Vec3::new(0.5, 0.0, 0.5), Vec3::new(0.5, 0.0, -0.5)];
+
+        for diagonal in diagonals {
+            let vertex_start = vertices.len() as u32;
+            let positions = [
+                base_pos - diagonal - up * 0.5, // Bottom Left
+                base_pos + diagonal - up * 0.5, // Bottom Right
+                base_pos + diagonal + up * 0.5, // Top Right
+                base_pos - diagonal + up * 0.5, // Top Left
+            ];
+            let tex_coords = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+
+            for (pos, tex_coords) in positions.iter().zip(tex_coords) {
+                vertices.push(Vertex {
+                    position: [pos.x, pos.y, pos.z],
+                    tex_coords,
+                    // light plants like the ground they stand on
+                    normal: [up.x, up.y, up.z],
+                });
+            }
+
+            indices.extend_from_slice(&[
+                vertex_start,
+                vertex_start + 1,
+                vertex_start + 2,
+                vertex_start,
+                vertex_start + 2,
+                vertex_start + 3,
+            ]);
+        }
+
+        (vertices, indices)
+    }
+
     fn add_face(vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>, base_pos: Vec3, face_dir: FaceDir) {
+        let (normal, _, _) = face_dir.get_axes();
+        // Move half a block in the direction of the normal instead
+        Self::add_quad(vertices, indices, base_pos + normal * 0.5, &face_dir, 1.0, 1.0);
+    }
+
+    // adds a face centred on `center`, `size_u` by `size_v` blocks big. the texture repeats once
+    // per block so merged faces look the same as the single faces they replace
+    pub fn add_quad(vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>, center: Vec3, face_dir: &FaceDir, size_u: f32, size_v: f32) {
         let (normal, u_axis, v_axis) = face_dir.get_axes();
         let vertex_start = vertices.len() as u32;
-        
-        // Move half a block in the direction of the normal instead
-        let corner_pos = base_pos + normal * 0.5;
+
+        let half_u = u_axis * (size_u * 0.5);
+        let half_v = v_axis * (size_v * 0.5);
         
         // Generate corners in the opposite winding order
         let positions = [
-            corner_pos - u_axis * 0.5 - v_axis * 0.5, // Bottom Left
-            corner_pos + u_axis * 0.5 - v_axis * 0.5, // Bottom Right
-            corner_pos + u_axis * 0.5 + v_axis * 0.5, // Top Right
-            corner_pos - u_axis * 0.5 + v_axis * 0.5, // Top Left
+            center - half_u - half_v, // Bottom Left
+            center + half_u - half_v, // Bottom Right
+            center + half_u + half_v, // Top Right
+            center - half_u + half_v, // Top Left
         ];
         
         // Add vertices with texture coordinates and normals
         for (i, pos) in positions.iter().enumerate() {
             let tex_coords = match i {
                 0 => [0.0, 0.0], // Bottom Left
-                1 => [1.0, 0.0], // Bottom Right
-                2 => [1.0, 1.0], // Top Right
-                3 => [0.0, 1.0], // Top Left
+                1 => [size_u, 0.0], // Bottom Right
+                2 => [size_u, size_v], // Top Right
+                3 => [0.0, size_v], // Top Left
                 _ => unreachable!(),
             };
             
@@ -0,0 +1,143 @@
+use noise::{NoiseFn, Perlin};
+
+use crate::{block::BlockType, chunk::Chunk, terrain::WorldSeed};
+
+/// Surface height of every column in a chunk plus a one column border around it, indexed
+/// [x + 1][z + 1], so carving can see water in the neighbouring chunks.
+pub type ColumnHeights = [[i32; 18]; 18];
+
+/// Tunables for cave carving.
+#[derive(Clone, Copy, Debug)]
+pub struct CaveConfig {
+    // how often tunnels show up, in noise cycles per block
+    pub frequency: f64,
+    // how wide tunnels are, larger means fatter caves (0.0 - 1.0)
+    pub thickness: f64,
+    // tunnels are only carved between these world y values
+    pub min_y: i32,
+    pub max_y: i32,
+}
+
+impl Default for CaveConfig {
+    fn default() -> Self {
+        CaveConfig { frequency: 0.03, thickness: 0.07, min_y: -44, max_y: 48 }
+    }
+}
+
+/// Carves worm-like tunnels where two 3D noise fields are both close to zero.
+/// Everything is sampled in world space so tunnels carry on across chunk borders.
+pub struct CaveCarver {
+    pub config: CaveConfig,
+    noise_a: Perlin,
+    noise_b: Perlin,
+}
+
+impl CaveCarver {
+    pub fn new(seed: WorldSeed, config: CaveConfig) -> Self {
+        CaveCarver {
+            config,
+            noise_a: Perlin::new(seed.0.wrapping_add(1)),
+            noise_b: Perlin::new(seed.0.wrapping_add(2)),
+        }
+    }
+
+    pub fn is_cave(&self, world_pos: [i32; 3]) -> bool {
+        let y = world_pos[1];
+        if y < self.config.min_y || y > self.config.max_y {
+            return false;
+        }
+
+        // taper the tunnels off over the last few blocks of the range instead of cutting them flat
+        let taper_blocks = 8.0;
+        let edge_distance = (y - self.config.min_y).min(self.config.max_y - y) as f64;
+        let thickness = self.config.thickness * (edge_distance / taper_blocks).min(1.0);
+
+        let point = [
+            world_pos[0] as f64 * self.config.frequency,
+            // squash vertically so tunnels run mostly sideways
+            world_pos[1] as f64 * self.config.frequency * 2.0,
+            world_pos[2] as f64 * self.config.frequency,
+        ];
+
+        self.noise_a.get(point).abs() < thickness && self.noise_b.get(point).abs() < thickness
+    }
+
+    // `heights` and `sea_level` say where the generated water is. blocks with water above them or
+    // beside them are left alone, otherwise the water would hang over or next to the cave
+    pub fn carve(&self, chunk: &mut Chunk, heights: &ColumnHeights, sea_level: i32) {
+        let world_origin = [chunk.origin[0] * 16, chunk.origin[1] * 16, chunk.origin[2] * 16];
+
+        // skip chunks entirely outside the vertical range
+        if world_origin[1] + 15 < self.config.min_y || world_origin[1] > self.config.max_y {
+            return;
+        }
+
+        // `x` and `z` are offset by one for the border
+        let is_water = |x: usize, z: usize, world_y: i32| world_y > heights[x][z] && world_y < sea_level;
+
+        for x in 0..16 {
+            for z in 0..16 {
+                let (bx, bz) = (x + 1, z + 1);
+                for y in 0..16 {
+                    let block_type = chunk.get(x, y, z);
+                    if matches!(block_type, BlockType::Air | BlockType::Bedrock | BlockType::Water) {
+                        continue;
+                    }
+                    let world_pos = [world_origin[0] + x as i32, world_origin[1] + y as i32, world_origin[2] + z as i32];
+                    let next_to_water = is_water(bx, bz, world_pos[1] + 1)
+                        || [(bx - 1, bz), (bx + 1, bz), (bx, bz - 1), (bx, bz + 1)]
+                            .into_iter()
+                            .any(|(nx, nz)| is_water(nx, nz, world_pos[1]));
+                    if next_to_water {
+                        continue;
+                    }
+
+                    if self.is_cave(world_pos) {
+                        chunk.set(x, y, z, BlockType::Air);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::array;
+
+    use crate::biome::{Biome, ColumnSample};
+
+    use super::*;
+
+    #[test]
+    fn sea_floor_stays_sealed_when_the_water_is_in_the_chunk_above() {
+        // thick enough that every block in range counts as cave
+        let config = CaveConfig { frequency: 0.03, thickness: 10.0, min_y: -100, max_y: 100 };
+        let carver = CaveCarver::new(WorldSeed(3), config);
+        let sea_level = 22;
+        // sea floor at 15, the top block of chunk y 0, for x up to 7 and in the next chunk over
+        // at x 16. the rest is dry land up to 30
+        let heights: ColumnHeights = array::from_fn(|bx| array::from_fn(|_| if bx <= 8 || bx == 17 { 15 } else { 30 }));
+
+        let mut below = Chunk::from_fn([0, 0, 0], |_, _, _| BlockType::Stone);
+        carver.carve(&mut below, &heights, sea_level);
+        let mut above = Chunk::from_fn([0, 1, 0], |_, _, _| BlockType::Stone);
+        carver.carve(&mut above, &heights, sea_level);
+
+        for z in 0..16 {
+            // the floor stays under its water, which starts in the chunk above
+            for x in 0..8 {
+                assert_eq!(below.get(x, 15, z), BlockType::Stone);
+                assert_eq!(below.get(x, 14, z), BlockType::Air);
+            }
+            // the land beside the water stays as long as there is water next to it, y 16 to 21
+            for x in [8, 15] {
+                assert!((0..6).all(|y| above.get(x, y, z) == BlockType::Stone), "x {} z {}", x, z);
+                assert_eq!(above.get(x, 6, z), BlockType::Air);
+                assert_eq!(below.get(x, 15, z), BlockType::Air);
+            }
+            // away from the water it all goes
+            assert_eq!(above.get(10, 0, z), BlockType::Air);
+        }
+    }
+}
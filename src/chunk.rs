@@ -1,67 +1,319 @@
-use std::array;
-
-use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+use std::{array, mem::size_of};
 
 use crate::block::{Block, BlockType};
 
+const CHUNK_VOLUME: usize = 16 * 16 * 16;
+
+// palettes bigger than this switch to one byte per block, packing stops paying off around here
+const MAX_PALETTE_LEN: usize = 16;
+
+/// How a chunk keeps its block types. Positions are never stored, they follow from the chunk
+/// origin and the index (x * 256 + y * 16 + z).
+#[derive(Clone)]
+enum BlockStorage {
+    // nothing but air, no allocation at all
+    Empty,
+    // the distinct block types plus `bits` wide indices into them packed into u64s (never
+    // straddling two of them). a uniform chunk has a single entry and no data at all
+    Paletted { palette: Vec<BlockType>, bits: u32, data: Vec<u64> },
+    // one id per block for chunks with too many different types
+    Flat(Box<[u8; CHUNK_VOLUME]>),
+}
+
+// bits needed to index a palette of `len` entries
+fn bits_for(len: usize) -> u32 {
+    if len <= 1 { 0 } else { usize::BITS - (len - 1).leading_zeros() }
+}
+
+fn longs_for(bits: u32) -> usize {
+    if bits == 0 { 0 } else { CHUNK_VOLUME.div_ceil(64 / bits as usize) }
+}
+
+fn read_packed(data: &[u64], bits: u32, index: usize) -> usize {
+    if bits == 0 {
+        return 0;
+    }
+    let per_long = 64 / bits as usize;
+    let shift = (index % per_long) as u32 * bits;
+    ((data[index / per_long] >> shift) & ((1 << bits) - 1)) as usize
+}
+
+fn write_packed(data: &mut [u64], bits: u32, index: usize, value: usize) {
+    // a single entry palette has nothing to store, index 0 is implied
+    if bits == 0 {
+        return;
+    }
+    let per_long = 64 / bits as usize;
+    let shift = (index % per_long) as u32 * bits;
+    let mask = ((1u64 << bits) - 1) << shift;
+    let long = &mut data[index / per_long];
+    *long = (*long & !mask) | ((value as u64) << shift);
+}
+
+impl BlockStorage {
+    // picks the smallest representation for a full set of block ids
+    fn from_ids(ids: &[u8; CHUNK_VOLUME]) -> Self {
+        let mut palette: Vec<u8> = Vec::new();
+        for id in ids {
+            if !palette.contains(id) {
+                palette.push(*id);
+                if palette.len() > MAX_PALETTE_LEN {
+                    return BlockStorage::Flat(Box::new(*ids));
+                }
+            }
+        }
+
+        if palette == [BlockType::Air.id()] {
+            return BlockStorage::Empty;
+        }
+
+        let bits = bits_for(palette.len());
+        let mut data = vec![0; longs_for(bits)];
+        if bits > 0 {
+            for (index, id) in ids.iter().enumerate() {
+                let palette_index = palette.iter().position(|entry| entry == id).unwrap();
+                write_packed(&mut data, bits, index, palette_index);
+            }
+        }
+
+        let palette = palette.into_iter().map(|id| BlockType::from_id(id).unwrap()).collect();
+        BlockStorage::Paletted { palette, bits, data }
+    }
+
+    fn get(&self, index: usize) -> BlockType {
+        match self {
+            BlockStorage::Empty => BlockType::Air,
+            BlockStorage::Paletted { palette, bits, data } => palette[read_packed(data, *bits, index)],
+            BlockStorage::Flat(ids) => BlockType::from_id(ids[index]).unwrap(),
+        }
+    }
+
+    fn set(&mut self, index: usize, block_type: BlockType) {
+        match self {
+            BlockStorage::Empty => {
+                if block_type == BlockType::Air {
+                    return;
+                }
+                *self = BlockStorage::Paletted { palette: vec![BlockType::Air], bits: 0, data: Vec::new() };
+                self.set(index, block_type);
+            }
+            BlockStorage::Paletted { palette, bits, data } => {
+                let palette_index = match palette.iter().position(|entry| *entry == block_type) {
+                    Some(palette_index) => palette_index,
+                    None => {
+                        if palette.len() == MAX_PALETTE_LEN {
+                            *self = BlockStorage::Flat(Box::new(self.ids()));
+                            self.set(index, block_type);
+                            return;
+                        }
+                        palette.push(block_type);
+
+                        // grow the indices when the palette outgrows them
+                        let needed = bits_for(palette.len());
+                        if needed > *bits {
+                            let mut repacked = vec![0; longs_for(needed)];
+                            for i in 0..CHUNK_VOLUME {
+                                write_packed(&mut repacked, needed, i, read_packed(data, *bits, i));
+                            }
+                            *data = repacked;
+                            *bits = needed;
+                        }
+                        palette.len() - 1
+                    }
+                };
+                write_packed(data, *bits, index, palette_index);
+            }
+            BlockStorage::Flat(ids) => ids[index] = block_type.id(),
+        }
+    }
+
+    fn ids(&self) -> [u8; CHUNK_VOLUME] {
+        array::from_fn(|index| self.get(index).id())
+    }
+
+    fn heap_size(&self) -> usize {
+        match self {
+            BlockStorage::Empty => 0,
+            BlockStorage::Paletted { palette, data, .. } => {
+                palette.capacity() * size_of::<BlockType>() + data.capacity() * size_of::<u64>()
+            }
+            BlockStorage::Flat(_) => CHUNK_VOLUME,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Chunk {
-    pub blocks: [[[Block; 16]; 16]; 16],
     pub origin: [i32; 3],
+    storage: BlockStorage,
 }
 
 
 impl Chunk {
-    pub fn new(origin: [i32;3]) -> Self { // accepts a relative chunk origin
-
-       let chunk_size = 16;
-        let world_origin = [origin[0] * chunk_size, origin[1] * chunk_size, origin[2] * chunk_size ];
-        //println!("New chunk at {}, {}, {}", world_origin[0], world_origin[1], world_origin[2]);
-       let scale = 150.0;
-        // Pre-calculate heights for all x,z coordinates
-        let heights: [[i32; 16]; 16] = array::from_fn(|x| 
-            array::from_fn(|z| {
-                let fbm = Fbm::<Perlin>::new(0)
-                .set_octaves(4)
-                .set_persistence(0.6)
-                .set_frequency(0.7)
-                .set_lacunarity(2.2);
-
-     
-                
-                let world_x = world_origin[0] as f64 + x as f64;
-                let world_z = world_origin[2] as f64 + z as f64;
-                
-                let noise_value = fbm.get([world_x / scale, world_z / scale]);
-                // Transform from [-1, 1] to [0, 1] then scale to reasonable height
-                let height = ((noise_value + 1.0) * 0.5 * 60.0) as i32;
-                height // This will give heights roughly in the range [0, 32]
-            })
-        );
-
-        let blocks = array::from_fn(|x| 
-            array::from_fn(|y|
-                array::from_fn(|z| {
-                    let block_x = (world_origin[0] + x as i32) as f64;
-                    let block_y = (world_origin[1] + y as i32) as f64;
-                    let block_z = (world_origin[2] + z as i32) as f64;
-                    
-                    
-                    let block_type = if heights[x][z] >= world_origin[1] + y as i32 {
-                        BlockType::Grass
-                    } else {
-                        BlockType::Air
-                    };
-
-                    Block::new(
-                        [block_x as f32, 
-                         block_y as f32, 
-                         block_z as f32],
-                        block_type)
-                })
-            )
-        );
-
-        Chunk { blocks, origin }
-    }
-}
\ No newline at end of file
+    // builds a chunk by asking `block_at` for the type of every block, using local x, y, z
+    pub fn from_fn(origin: [i32;3], block_at: impl Fn(usize, usize, usize) -> BlockType) -> Self { // accepts a relative chunk origin
+        let ids = array::from_fn(|index| block_at(index / 256, (index / 16) % 16, index % 16).id());
+        Chunk { origin, storage: BlockStorage::from_ids(&ids) }
+    }
+
+    pub fn empty(origin: [i32; 3]) -> Self {
+        Chunk { origin, storage: BlockStorage::Empty }
+    }
+
+    fn index(x: usize, y: usize, z: usize) -> usize {
+        x * 256 + y * 16 + z
+    }
+
+    pub fn get(&self, x: usize, y: usize, z: usize) -> BlockType {
+        self.storage.get(Chunk::index(x, y, z))
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, z: usize, block_type: BlockType) {
+        self.storage.set(Chunk::index(x, y, z), block_type);
+    }
+
+    // the block at a local position along with its world position, for meshing
+    pub fn block(&self, x: usize, y: usize, z: usize) -> Block {
+        let pos = [
+            (self.origin[0] * 16 + x as i32) as f32,
+            (self.origin[1] * 16 + y as i32) as f32,
+            (self.origin[2] * 16 + z as i32) as f32,
+        ];
+        Block::new(pos, self.get(x, y, z))
+    }
+
+    // true when the chunk is known to be all air without looking at every block
+    pub fn is_empty(&self) -> bool {
+        matches!(self.storage, BlockStorage::Empty)
+    }
+
+    // re-picks the smallest storage, worth calling after a lot of edits (e.g. at the end of generation)
+    pub fn compact(&mut self) {
+        self.storage = BlockStorage::from_ids(&self.storage.ids());
+    }
+
+    // bytes used by this chunk, including its heap allocations
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Chunk>() + self.storage.heap_size()
+    }
+
+    // origin as three little endian i32s, then one block id per block in x, y, z order
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(12 + CHUNK_VOLUME);
+        for coord in self.origin {
+            bytes.extend_from_slice(&coord.to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.storage.ids());
+        bytes
+    }
+
+    // None if the data is the wrong size or contains an unknown block id
+    pub fn from_bytes(bytes: &[u8]) -> Option<Chunk> {
+        if bytes.len() != 12 + CHUNK_VOLUME {
+            return None;
+        }
+
+        let origin: [i32; 3] = array::from_fn(|i| i32::from_le_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap()));
+        let ids: [u8; CHUNK_VOLUME] = bytes[12..].try_into().unwrap();
+        if ids.iter().any(|&id| BlockType::from_id(id).is_none()) {
+            return None;
+        }
+
+        Some(Chunk { origin, storage: BlockStorage::from_ids(&ids) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use super::*;
+
+    // every block type somewhere, more than the palette can hold
+    fn mixed(x: usize, y: usize, z: usize) -> BlockType {
+        let types: Vec<BlockType> = BlockType::iter().collect();
+        types[(x * 7 + y * 3 + z) % types.len()]
+    }
+
+    fn assert_same_blocks(a: &Chunk, b: &Chunk) {
+        for x in 0..16 {
+            for y in 0..16 {
+                for z in 0..16 {
+                    assert_eq!(a.get(x, y, z), b.get(x, y, z), "block {} {} {}", x, y, z);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn setting_uniform_chunk_to_its_own_type_is_a_no_op() {
+        for block_type in [BlockType::Stone, BlockType::Water] {
+            let mut chunk = Chunk::from_fn([0, 0, 0], |_, _, _| block_type);
+            chunk.set(1, 2, 3, block_type);
+            assert_eq!(chunk.get(1, 2, 3), block_type);
+            assert_eq!(chunk.get(0, 0, 0), block_type);
+        }
+    }
+
+    #[test]
+    fn set_then_get_through_palette_growth_and_flat_fallback() {
+        let mut chunk = Chunk::empty([0, 0, 0]);
+        let reference = Chunk::from_fn([0, 0, 0], mixed);
+        for x in 0..16 {
+            for y in 0..16 {
+                for z in 0..16 {
+                    chunk.set(x, y, z, mixed(x, y, z));
+                }
+            }
+        }
+        assert_same_blocks(&chunk, &reference);
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let chunks = [
+            Chunk::empty([1, -2, 3]),
+            Chunk::from_fn([-4, 0, 7], |_, _, _| BlockType::Stone),
+            Chunk::from_fn([0, 5, -1], |_, y, _| if y < 8 { BlockType::Dirt } else { BlockType::Air }),
+            Chunk::from_fn([2, 2, 2], mixed),
+        ];
+        for chunk in &chunks {
+            let restored = Chunk::from_bytes(&chunk.to_bytes()).unwrap();
+            assert_eq!(restored.origin, chunk.origin);
+            assert_same_blocks(&restored, chunk);
+        }
+
+        assert!(Chunk::from_bytes(&[0; 10]).is_none());
+        let mut bad = chunks[0].to_bytes();
+        bad[12] = 255;
+        assert!(Chunk::from_bytes(&bad).is_none());
+    }
+
+    #[test]
+    fn memory_usage_follows_contents() {
+        let empty = Chunk::empty([0, 0, 0]);
+        let uniform = Chunk::from_fn([0, 0, 0], |_, _, _| BlockType::Stone);
+        let layered = Chunk::from_fn([0, 0, 0], |_, y, _| if y < 8 { BlockType::Dirt } else { BlockType::Air });
+        let flat = Chunk::from_fn([0, 0, 0], mixed);
+
+        assert_eq!(empty.memory_usage(), size_of::<Chunk>());
+        assert!(uniform.memory_usage() < layered.memory_usage());
+        // two types only need one bit per block
+        assert!(layered.memory_usage() < size_of::<Chunk>() + CHUNK_VOLUME / 8 + 64);
+        assert!(layered.memory_usage() < flat.memory_usage());
+        assert_eq!(flat.memory_usage(), size_of::<Chunk>() + CHUNK_VOLUME);
+
+        // carving everything out and compacting gives the memory back
+        let mut carved = layered.clone();
+        for x in 0..16 {
+            for y in 0..8 {
+                for z in 0..16 {
+                    carved.set(x, y, z, BlockType::Air);
+                }
+            }
+        }
+        carved.compact();
+        assert!(carved.is_empty());
+        assert_eq!(carved.memory_usage(), size_of::<Chunk>());
+    }
+}
@@ -0,0 +1,141 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::chunk_manager::ChunkMeshData;
+
+/// What `ChunkBufferCache::sync` did, mostly useful for logging and tests.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SyncPlan {
+    // chunks whose mesh is new or changed since it was last uploaded
+    pub upload: Vec<[i32; 3]>,
+    // chunks that are gone and had their buffers dropped
+    pub free: Vec<[i32; 3]>,
+}
+
+/// One set of GPU buffers per chunk. Only keeps track of *which* meshes need uploading or freeing,
+/// the buffers themselves are whatever the `upload` callback returns, so this works without a GPU.
+pub struct ChunkBufferCache<B> {
+    // mesh version each buffer was built from
+    buffers: HashMap<[i32; 3], (u64, B)>,
+}
+
+impl<B> Default for ChunkBufferCache<B> {
+    fn default() -> Self {
+        ChunkBufferCache { buffers: HashMap::new() }
+    }
+}
+
+impl<B> ChunkBufferCache<B> {
+    // compares the current meshes against what is uploaded without touching anything
+    pub fn plan(&self, meshes: &HashMap<[i32; 3], Arc<ChunkMeshData>>) -> SyncPlan {
+        let mut plan = SyncPlan::default();
+
+        for (origin, mesh) in meshes {
+            match self.buffers.get(origin) {
+                Some((version, _)) if *version == mesh.version => {}
+                _ => plan.upload.push(*origin),
+            }
+        }
+        for origin in self.buffers.keys() {
+            if !meshes.contains_key(origin) {
+                plan.free.push(*origin);
+            }
+        }
+
+        plan
+    }
+
+    // makes the buffers match `meshes`: uploads new and changed meshes, frees the missing ones
+    pub fn sync(&mut self, meshes: &HashMap<[i32; 3], Arc<ChunkMeshData>>, mut upload: impl FnMut(&ChunkMeshData) -> B) -> SyncPlan {
+        let plan = self.plan(meshes);
+
+        for origin in &plan.free {
+            self.buffers.remove(origin);
+        }
+        for origin in &plan.upload {
+            let mesh = &meshes[origin];
+            self.buffers.insert(*origin, (mesh.version, upload(mesh)));
+        }
+
+        plan
+    }
+
+    pub fn buffers(&self) -> impl Iterator<Item = &B> {
+        self.buffers.values().map(|(_, buffers)| buffers)
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffers.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chunk_manager::MeshBuffers;
+
+    use super::*;
+
+    fn mesh() -> Arc<ChunkMeshData> {
+        Arc::new(ChunkMeshData::new(MeshBuffers::default(), MeshBuffers::default()))
+    }
+
+    fn sorted(mut origins: Vec<[i32; 3]>) -> Vec<[i32; 3]> {
+        origins.sort();
+        origins
+    }
+
+    #[test]
+    fn uploads_only_new_and_changed_meshes_and_frees_removed_ones() {
+        let mut cache: ChunkBufferCache<u64> = ChunkBufferCache::default();
+        let mut meshes = HashMap::from([([0, 0, 0], mesh()), ([1, 0, 0], mesh()), ([2, 0, 0], mesh())]);
+
+        // everything is new
+        let plan = cache.sync(&meshes, |mesh| mesh.version);
+        assert_eq!(sorted(plan.upload), vec![[0, 0, 0], [1, 0, 0], [2, 0, 0]]);
+        assert!(plan.free.is_empty());
+        assert_eq!(cache.len(), 3);
+
+        // nothing changed
+        assert_eq!(cache.plan(&meshes), SyncPlan::default());
+        assert_eq!(cache.sync(&meshes, |_| panic!("nothing should upload")), SyncPlan::default());
+
+        // one remeshed, one added, one removed
+        meshes.insert([1, 0, 0], mesh());
+        meshes.insert([3, 0, 0], mesh());
+        meshes.remove(&[2, 0, 0]);
+
+        let planned = cache.plan(&meshes);
+        assert_eq!(sorted(planned.upload.clone()), vec![[1, 0, 0], [3, 0, 0]]);
+        assert_eq!(planned.free, vec![[2, 0, 0]]);
+        // planning alone leaves the buffers alone
+        assert_eq!(cache.len(), 3);
+
+        let mut uploaded = Vec::new();
+        let plan = cache.sync(&meshes, |mesh| {
+            uploaded.push(mesh.version);
+            mesh.version
+        });
+        assert_eq!(sorted(plan.upload), sorted(planned.upload));
+        assert_eq!(plan.free, planned.free);
+        assert_eq!(uploaded.len(), 2);
+
+        // the cache now holds exactly the current versions
+        let mut held: Vec<u64> = cache.buffers().copied().collect();
+        let mut expected: Vec<u64> = meshes.values().map(|mesh| mesh.version).collect();
+        held.sort();
+        expected.sort();
+        assert_eq!(held, expected);
+    }
+
+    #[test]
+    fn empty_meshes_free_everything() {
+        let mut cache: ChunkBufferCache<()> = ChunkBufferCache::default();
+        cache.sync(&HashMap::from([([0, 0, 0], mesh())]), |_| ());
+        let plan = cache.sync(&HashMap::new(), |_| ());
+        assert_eq!(plan.free, vec![[0, 0, 0]]);
+        assert!(cache.is_empty());
+    }
+}
@@ -1,21 +1,91 @@
-use std::{collections::{HashMap, HashSet}, sync::{mpsc::Sender, Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard}};
+use std::{collections::{HashMap, HashSet}, sync::{atomic::{AtomicU64, Ordering}, mpsc::Sender, Arc, Mutex, RwLock}};
 use nalgebra_glm::Vec3;
-use crate::{block::{Block, BlockType, FaceDir, Vertex}, chunk::Chunk};
+use crate::{block::{Block, BlockType, Vertex}, chunk::Chunk, chunk_store::ChunkStore, decoration::PendingEdit, mesher::{BorderFaces, Mesher}, world_save::LevelData};
+
+/// The box of chunk origins the manager is currently keeping loaded, inclusive on both ends.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LoadedArea {
+    pub min: [i32; 3],
+    pub max: [i32; 3],
+}
+
+impl LoadedArea {
+    pub fn contains(&self, origin: [i32; 3]) -> bool {
+        (0..3).all(|axis| origin[axis] >= self.min[axis] && origin[axis] <= self.max[axis])
+    }
+
+    // inside and not on the edge, so every face neighbour is part of the area too
+    pub fn contains_strictly(&self, origin: [i32; 3]) -> bool {
+        (0..3).all(|axis| origin[axis] > self.min[axis] && origin[axis] < self.max[axis])
+    }
+}
+
+// features waiting for (or already placed in) a chunk: chunk origin -> world position -> block
+pub type PendingEdits = HashMap<[i32; 3], HashMap<[i32; 3], BlockType>>;
 
 pub struct ChunkManager {
-    pub chunks: Arc<RwLock<HashMap<[i32; 3], Chunk>>>,
+    // chunks are shared, handing the map to the mesher only copies pointers
+    pub chunks: Arc<RwLock<HashMap<[i32; 3], Arc<Chunk>>>>,
+    // features that spill over into other chunks, keyed by the chunk they land in. they are kept
+    // after being applied so a chunk that is unloaded and regenerated gets them back, until the
+    // player is far enough away that every chunk involved gets regenerated anyway
+    pub pending_edits: Arc<Mutex<PendingEdits>>,
     pub task_sender: Sender<WorkerMessage>,
+    // straight to the mesh worker, so edits don't wait behind chunk generation
+    pub buffer_sender: Sender<BufferTask>,
+    // None until the first update_chunks
+    pub loaded_area: Arc<RwLock<Option<LoadedArea>>>,
+    // loaded chunks the player changed, plus chunks that came out of the store. they stay in here
+    // until they unload, so generated features leave them alone even after an autosave
+    pub modified: Arc<Mutex<HashSet<[i32; 3]>>>,
+    // loaded chunks the player changed since they were last copied into the store
+    pub unsaved: Arc<Mutex<HashSet<[i32; 3]>>>,
+    pub store: Arc<ChunkStore>,
 }
 
-pub struct ChunkMeshData {
+#[derive(Default, Clone)]
+pub struct MeshBuffers {
     pub vertices: Vec<Vertex>,
     pub indices: Vec<u32>,
 }
 
+impl MeshBuffers {
+    // appends another mesh, shifting its indices past our existing vertices
+    pub fn extend(&mut self, vertices: &[Vertex], indices: &[u32]) {
+        let vertex_offset = self.vertices.len() as u32;
+        self.vertices.extend_from_slice(vertices);
+        self.indices.extend(indices.iter().map(|i| i + vertex_offset));
+    }
+}
+
+pub struct ChunkMeshData {
+    pub opaque: MeshBuffers,
+    // water and other see-through blocks, drawn after all opaque geometry
+    pub translucent: MeshBuffers,
+    // unique per mesh build, tells the renderer whether its uploaded copy is stale
+    pub version: u64,
+}
+
+static NEXT_MESH_VERSION: AtomicU64 = AtomicU64::new(0);
+
+impl ChunkMeshData {
+    pub fn new(opaque: MeshBuffers, translucent: MeshBuffers) -> Self {
+        let version = NEXT_MESH_VERSION.fetch_add(1, Ordering::Relaxed);
+        ChunkMeshData { opaque, translucent, version }
+    }
+}
+
 pub struct LoadChunkTask {
     pub origins: Vec<[i32;3]>,
-    pub chunk_map: Arc<RwLock<HashMap<[i32; 3], Chunk>>>,
-    pub mesh_map:  Arc<RwLock<HashMap<[i32; 3], ChunkMeshData>>>,
+    // chunk the player is in and how far out chunks are kept, for dropping generator caches
+    pub center: [i32; 3],
+    pub render_distance: i32,
+    pub chunk_map: Arc<RwLock<HashMap<[i32; 3], Arc<Chunk>>>>,
+    pub mesh_map:  Arc<RwLock<HashMap<[i32; 3], Arc<ChunkMeshData>>>>,
+    pub pending_edits: Arc<Mutex<PendingEdits>>,
+    pub modified: Arc<Mutex<HashSet<[i32; 3]>>>,
+    // checked before generating, chunks found here come back as the player left them
+    pub store: Arc<ChunkStore>,
 }
 
 pub enum WorkerMessage {
@@ -23,11 +93,111 @@ pub enum WorkerMessage {
     Shutdown,
 }
 
+pub enum BufferTask {
+    // chunks whose mesh is stale and has to be rebuilt
+    UpdateBuffers(HashSet<[i32; 3]>),
+    Shutdown,
+}
+
+pub enum SaveTask {
+    // flush the chunk store to disk and write this level file
+    Autosave(LevelData),
+    Shutdown,
+}
+
 impl ChunkManager {
-    pub fn new(task_sender: Sender<WorkerMessage>) -> Self {
+    pub fn new(task_sender: Sender<WorkerMessage>, buffer_sender: Sender<BufferTask>) -> Self {
         ChunkManager {
             chunks: Arc::new(RwLock::new(HashMap::new())),
+            pending_edits: Arc::new(Mutex::new(HashMap::new())),
             task_sender,
+            buffer_sender,
+            loaded_area: Arc::new(RwLock::new(None)),
+            modified: Arc::new(Mutex::new(HashSet::new())),
+            unsaved: Arc::new(Mutex::new(HashSet::new())),
+            store: Arc::new(ChunkStore::new()),
+        }
+    }
+
+    // the six chunks sharing a face with `origin`
+    pub fn face_neighbors(origin: [i32; 3]) -> [[i32; 3]; 6] {
+        [
+            [origin[0] + 1, origin[1], origin[2]],
+            [origin[0] - 1, origin[1], origin[2]],
+            [origin[0], origin[1] + 1, origin[2]],
+            [origin[0], origin[1] - 1, origin[2]],
+            [origin[0], origin[1], origin[2] + 1],
+            [origin[0], origin[1], origin[2] - 1],
+        ]
+    }
+
+    // chunks whose mesh depends on the block at `world_pos`: its own, plus the neighbour across
+    // any chunk border the block sits on
+    pub fn chunks_touching_block(world_pos: [i32; 3]) -> HashSet<[i32; 3]> {
+        let origin = world_pos.map(|coord| coord.div_euclid(16));
+        let mut touched = HashSet::from([origin]);
+
+        for axis in 0..3 {
+            let mut neighbor = origin;
+            match world_pos[axis].rem_euclid(16) {
+                0 => neighbor[axis] -= 1,
+                15 => neighbor[axis] += 1,
+                _ => continue,
+            }
+            touched.insert(neighbor);
+        }
+        touched
+    }
+
+    // asks the mesh worker to rebuild these chunks
+    pub fn mark_dirty(&self, origins: HashSet<[i32; 3]>) {
+        if !origins.is_empty() {
+            self.buffer_sender.send(BufferTask::UpdateBuffers(origins)).unwrap();
+        }
+    }
+
+    // changes a single block and remeshes whatever can see it. false if its chunk isn't loaded
+    pub fn set_block(&self, world_pos: [i32; 3], block_type: BlockType) -> bool {
+        self.set_blocks([(world_pos, block_type)]) == 1
+    }
+
+    // set_block for many blocks at once, taking the lock and remeshing each chunk only once.
+    // blocks in chunks that aren't loaded are skipped, returns how many were set
+    pub fn set_blocks(&self, edits: impl IntoIterator<Item = ([i32; 3], BlockType)>) -> usize {
+        let mut chunks = self.chunks.write().unwrap();
+        let mut changed = HashSet::new();
+        let mut dirty = HashSet::new();
+        let mut count = 0;
+
+        for (world_pos, block_type) in edits {
+            let origin = world_pos.map(|coord| coord.div_euclid(16));
+            let [x, y, z] = world_pos.map(|coord| coord.rem_euclid(16) as usize);
+            let Some(chunk) = chunks.get_mut(&origin) else {
+                continue;
+            };
+            // copies the chunk only if the mesher is still holding on to the old one
+            Arc::make_mut(chunk).set(x, y, z, block_type);
+            changed.insert(origin);
+            dirty.extend(ChunkManager::chunks_touching_block(world_pos));
+            count += 1;
+        }
+        drop(chunks);
+
+        self.modified.lock().unwrap().extend(changed.iter().copied());
+        self.unsaved.lock().unwrap().extend(changed);
+        self.mark_dirty(dirty);
+        count
+    }
+
+    // copies every loaded chunk edited since the last stash into the store, cheap enough for the
+    // render thread. the slow disk write is left to whoever flushes the store
+    pub fn stash_modified(&self) {
+        let chunks = self.chunks.read().unwrap();
+        let mut unsaved = self.unsaved.lock().unwrap();
+        for origin in unsaved.drain() {
+            if let Some(chunk) = chunks.get(&origin) {
+                self.store.save(chunk);
+            }
         }
     }
 
@@ -46,7 +216,7 @@ impl ChunkManager {
 
 
 
-    pub fn update_chunks(&mut self, position: Vec3, mesh_map: Arc<RwLock<HashMap<[i32; 3], ChunkMeshData>>>) {
+    pub fn update_chunks(&mut self, position: Vec3, mesh_map: Arc<RwLock<HashMap<[i32; 3], Arc<ChunkMeshData>>>>) {
         println!("Updating chunks");
        
         let chunk_size: i32 = 16;
@@ -67,10 +237,35 @@ impl ChunkManager {
             .collect();
 
         let removed = chunks_to_remove.len();
-            
-        for key in chunks_to_remove {
-            chunks.remove(&key);
+
+        let mut meshes = mesh_map.write().unwrap();
+        let mut modified = self.modified.lock().unwrap();
+        let mut unsaved = self.unsaved.lock().unwrap();
+        for key in &chunks_to_remove {
+            if let Some(chunk) = chunks.remove(key) {
+                modified.remove(key);
+                // regenerating would lose the player's changes. anything else is either
+                // generated or already in the store as it is
+                if unsaved.remove(key) {
+                    self.store.save(&chunk);
+                }
+            }
+            meshes.remove(key);
         }
+        drop(unsaved);
+        drop(modified);
+        drop(meshes);
+
+        // a chunk whose neighbours are all unloaded can lose its queue, whatever spilled into it
+        // gets queued again when they are regenerated
+        self.pending_edits.lock().unwrap()
+            .retain(|origin, _| ChunkManager::chunk_in_range(*origin, user_chunk_pos, render_distance + 1));
+
+        // chunks left next to a removed one have to grow back the faces on that border
+        let dirty: HashSet<[i32; 3]> = chunks_to_remove.iter()
+            .flat_map(|key| ChunkManager::face_neighbors(*key))
+            .filter(|neighbor| chunks.contains_key(neighbor))
+            .collect();
 
         println!("{} chunks after removing {} chunks", chunks.len(), removed);
 
@@ -80,6 +275,16 @@ impl ChunkManager {
 
         drop(chunks);
 
+        if removed > 0 {
+            // always send, even with nothing to remesh, so the renderer frees the removed chunks
+            self.buffer_sender.send(BufferTask::UpdateBuffers(dirty)).unwrap();
+        }
+
+        // matches the loop below, which stops one chunk short on the positive side
+        *self.loaded_area.write().unwrap() = Some(LoadedArea {
+            min: user_chunk_pos.map(|coord| coord - render_distance),
+            max: user_chunk_pos.map(|coord| coord + render_distance - 1),
+        });
 
         let mut chunks_to_load: Vec<[i32;3]> = Vec::new();
 
@@ -117,11 +322,58 @@ impl ChunkManager {
         println!("generated {} tasks", chunks_to_load.len());
         self.task_sender.send(WorkerMessage::LoadChunkTask(LoadChunkTask{
             origins: chunks_to_load,
+            center: user_chunk_pos,
+            render_distance,
             chunk_map: self.chunks.clone(),
             mesh_map,
+            pending_edits: self.pending_edits.clone(),
+            modified: self.modified.clone(),
+            store: self.store.clone(),
         })).unwrap();
     }
 
+    // applies features queued for a freshly generated chunk, then hands the chunk's own spill-over
+    // to its neighbours: loaded ones are edited right away, the rest are queued.
+    // a neighbour only gets an edit the first time it is queued, so regenerating a chunk doesn't
+    // grow back leaves the player broke next door, and chunks in `modified` are never touched.
+    // returns the loaded neighbours that changed and need remeshing
+    pub fn place_features(
+        chunk: &mut Chunk,
+        spill: Vec<PendingEdit>,
+        chunks: &mut HashMap<[i32; 3], Arc<Chunk>>,
+        pending_edits: &Mutex<PendingEdits>,
+        modified: &Mutex<HashSet<[i32; 3]>>,
+    ) -> HashSet<[i32; 3]> {
+        let mut pending_edits = pending_edits.lock().unwrap();
+        let modified = modified.lock().unwrap();
+        let mut changed = HashSet::new();
+
+        if let Some(edits) = pending_edits.get(&chunk.origin) {
+            for (pos, block_type) in edits {
+                PendingEdit { pos: *pos, block_type: *block_type }.apply(chunk);
+            }
+        }
+
+        for edit in spill {
+            let target = edit.chunk_origin();
+
+            let queued = pending_edits.entry(target).or_default();
+            if queued.get(&edit.pos).is_some_and(|queued| !edit.overrides(*queued)) {
+                continue;
+            }
+
+            if let Some(neighbor) = chunks.get_mut(&target) {
+                if !modified.contains(&target) {
+                    edit.apply(Arc::make_mut(neighbor));
+                    changed.insert(target);
+                }
+            }
+            queued.insert(edit.pos, edit.block_type);
+        }
+
+        changed
+    }
+
 
     pub fn get_chunk_at(pos: [f32; 3]) -> [i32; 3] {
         let chunk_size = 16;
@@ -132,7 +384,7 @@ impl ChunkManager {
         ]
     }
     
-    fn get_block( world_pos: [i32; 3], chunks: &HashMap<[i32; 3], Chunk>) -> Option<Block> {
+    pub fn get_block( world_pos: [i32; 3], chunks: &HashMap<[i32; 3], Arc<Chunk>>) -> Option<Block> {
         let chunk_size = 16;
         // Calculate chunk origin and local coordinate
         let chunk_origin = [
@@ -150,121 +402,162 @@ impl ChunkManager {
 
         // test here
         chunks.get(&chunk_origin)
-            .map(|chunk| &chunk.blocks[local_pos[0]][local_pos[1]][local_pos[2]]).copied()
+            .map(|chunk| chunk.block(local_pos[0], local_pos[1], local_pos[2]))
     }
 
-    fn should_render_face( neighbor_pos: [i32; 3], block: &Block, chunks: &HashMap<[i32;3], Chunk>) -> bool {
+    // `missing_is_solid` decides what an unloaded neighbour chunk counts as
+    pub fn should_render_face( neighbor_pos: [i32; 3], block: &Block, chunks: &HashMap<[i32;3], Arc<Chunk>>, missing_is_solid: bool) -> bool {
         if let Some(neighbor) = ChunkManager::get_block(neighbor_pos, chunks) {
-            if neighbor.block_type != BlockType::Air{
-            //println!("Neighbor: {:?}\n Self: {:?}", neighbor, block);
-            }
-             neighbor.block_type == BlockType::Air
+            // faces are visible through anything see-through, but water next to water is one body
+            !neighbor.block_type.is_opaque() && neighbor.block_type != block.block_type
         } else {
-           // show faces at chunk borders?
-            true // reminder you have to do this atm to prevent weird behavior
+            !missing_is_solid
         }
     }
 
     pub fn shutdown_sender(&self) {
         self.task_sender.send(WorkerMessage::Shutdown).unwrap();
+        self.buffer_sender.send(BufferTask::Shutdown).unwrap();
     }
 
-    pub fn get_buffers(chunks: HashMap<[i32; 3], Chunk>, mesh_map: Arc<RwLock<HashMap<[i32;3], ChunkMeshData>>>) -> (Vec<Vertex>, Vec<u32> ) {
+    // (re)meshes the `dirty` chunks plus any loaded chunk without a cached mesh, then returns the
+    // meshes of all loaded chunks, which the renderer diffs against what it has already uploaded
+    pub fn get_buffers(chunks: &HashMap<[i32; 3], Arc<Chunk>>, dirty: &HashSet<[i32; 3]>, mesh_map: Arc<RwLock<HashMap<[i32; 3], Arc<ChunkMeshData>>>>, mesher: Mesher, borders: BorderFaces, area: Option<LoadedArea>) -> HashMap<[i32; 3], Arc<ChunkMeshData>> {
 
         let mut mesh_map = mesh_map.write().unwrap();
+        let mut meshes = HashMap::new();
 
-        let mut vertices: Vec<Vertex> = Vec::new();
-        let mut indices: Vec<u32> = Vec::new();
-        let mut vertex_offset: u32 = 0;
-
-        println!("get_buffers is locking chunks");
+        // anything unloaded since the last update loses its cached mesh
+        mesh_map.retain(|origin, _| chunks.contains_key(origin));
 
         for (origin, chunk) in chunks.iter() {
 
-            // define here so we can cache
-            let mut chunk_indices: Vec<u32> = Vec::new();
-            let mut chunk_vertices: Vec<Vertex> = Vec::new();
-
-            if mesh_map.contains_key(origin){
-                let chunk_mesh_data = mesh_map.get(origin).unwrap();
-                vertices.extend(chunk_mesh_data.vertices.clone());
-                indices.extend(chunk_mesh_data.indices.iter().map(|i| i + vertex_offset));
-                vertex_offset += chunk_mesh_data.vertices.len() as u32;
-                continue;
+            if dirty.contains(origin) || !mesh_map.contains_key(origin) {
+                // add to mesh map so we can cache
+                let missing_is_solid = borders.missing_is_solid(*origin, area);
+                mesh_map.insert(*origin, Arc::new(mesher.mesh_chunk(*origin, chunk, chunks, missing_is_solid)));
             }
 
-            let mut chunk_vertex_offset = 0;
+            meshes.insert(*origin, mesh_map[origin].clone());
+        }
+        meshes
+    }
+}
 
-            for x in 0..16 {
-                for y in 0..16 {
-                    for z in 0..16 {
-                        let block = &chunk.blocks[x][y][z];
-                        if block.block_type == BlockType::Air {
-                            continue;
-                        }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                        let chunk_size = 16; // todo test idk
-                        let world_x = origin[0] * chunk_size + x as i32;
-                        let world_y = origin[1] * chunk_size  + y as i32;
-                        let world_z = origin[2] *chunk_size + z as i32;
-
-                        // Check each face direction
-                        let mut faces_to_render = Vec::new();
-                        
-                        // Up face (checking above)
-                        if ChunkManager::should_render_face([world_x, world_y + 1, world_z], &block, &chunks) {
-                            faces_to_render.push(FaceDir::Up); 
-                        }
-                        // Down face (checking below)
-                        if ChunkManager::should_render_face([world_x, world_y - 1, world_z], &block, &chunks) {
-                            faces_to_render.push(FaceDir::Down); 
-                        }
-                        // Right face (now Left)
-                        if ChunkManager::should_render_face([world_x - 1, world_y, world_z], &block, &chunks) {
-                            faces_to_render.push(FaceDir::Left);
-                        }
-                        // Left face (now Right)
-                        if ChunkManager::should_render_face([world_x + 1, world_y, world_z], &block, &chunks) {
-                            faces_to_render.push(FaceDir::Right);
-                        }
-                        // Front face
-                        if ChunkManager::should_render_face([world_x, world_y, world_z - 1], &block, &chunks) {
-                            // println!("{} {} {}", world_x, world_y, world_z);
-                            faces_to_render.push(FaceDir::Front);
-                        }
-                        // Back face
-                        if ChunkManager::should_render_face([world_x, world_y, world_z + 1], &block, &chunks) {
-                            faces_to_render.push(FaceDir::Back);
-                        }
+    fn leaves_at(pos: [i32; 3]) -> PendingEdit {
+        PendingEdit { pos, block_type: BlockType::Leaves }
+    }
 
-                        // Generate only the necessary faces
-                        let (block_vertices, block_indices) = block.generate_faces(faces_to_render);
-                        
-                        // Add the vertices
-                        chunk_vertices.extend_from_slice(&block_vertices);
-                        
-                        // Add the indices with offset
-                        chunk_indices.extend(block_indices.iter().map(|i| i + chunk_vertex_offset as u32));
-                        
-                        chunk_vertex_offset += block_vertices.len() as u32;
-                    }
-                }
-            }
+    #[test]
+    fn regenerated_chunk_does_not_reapply_spill_to_loaded_neighbour() {
+        let neighbor = [1, 0, 0];
+        let mut chunks = HashMap::from([(neighbor, Arc::new(Chunk::empty(neighbor)))]);
+        let pending_edits = Mutex::new(HashMap::new());
+        let modified = Mutex::new(HashSet::new());
+        let spill = vec![leaves_at([16, 3, 3])];
+
+        let changed = ChunkManager::place_features(&mut Chunk::empty([0, 0, 0]), spill.clone(), &mut chunks, &pending_edits, &modified);
+        assert_eq!(changed, HashSet::from([neighbor]));
+        assert_eq!(chunks[&neighbor].get(0, 3, 3), BlockType::Leaves);
+
+        // the player breaks the leaves, then the chunk that grew them is regenerated
+        Arc::make_mut(chunks.get_mut(&neighbor).unwrap()).set(0, 3, 3, BlockType::Air);
+        let changed = ChunkManager::place_features(&mut Chunk::empty([0, 0, 0]), spill, &mut chunks, &pending_edits, &modified);
+        assert!(changed.is_empty());
+        assert_eq!(chunks[&neighbor].get(0, 3, 3), BlockType::Air);
+    }
 
-            vertices.extend(&chunk_vertices);
-            indices.extend(chunk_indices.iter().map(|i| i + vertex_offset));
+    #[test]
+    fn queued_edits_are_keyed_by_position_and_logs_win() {
+        let mut chunks = HashMap::new();
+        let pending_edits = Mutex::new(HashMap::new());
+        let modified = Mutex::new(HashSet::new());
+        let pos = [-1, 4, 4];
+        let log = PendingEdit { pos, block_type: BlockType::Log };
 
-            vertex_offset += chunk_vertex_offset;
+        for spill in [vec![leaves_at(pos)], vec![log], vec![leaves_at(pos)]] {
+            ChunkManager::place_features(&mut Chunk::empty([0, 0, 0]), spill, &mut chunks, &pending_edits, &modified);
+        }
+        let queued = &pending_edits.lock().unwrap()[&[-1, 0, 0]];
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[&pos], BlockType::Log);
+    }
 
-            // add to mesh map
-            let chunk_mesh_data = ChunkMeshData {
-                vertices: chunk_vertices,
-                indices: chunk_indices,
-            };
+    #[test]
+    fn spill_never_touches_modified_chunks() {
+        let neighbor = [0, 0, 1];
+        let mut chunks = HashMap::from([(neighbor, Arc::new(Chunk::empty(neighbor)))]);
+        let pending_edits = Mutex::new(HashMap::new());
+        let modified = Mutex::new(HashSet::from([neighbor]));
 
-            mesh_map.insert(*origin, chunk_mesh_data);
+        let changed = ChunkManager::place_features(&mut Chunk::empty([0, 0, 0]), vec![leaves_at([2, 2, 16])], &mut chunks, &pending_edits, &modified);
+        assert!(changed.is_empty());
+        assert_eq!(chunks[&neighbor].get(2, 2, 0), BlockType::Air);
+    }
+    #[test]
+    fn autosave_keeps_edited_chunks_out_of_reach_of_spill() {
+        let (task_sender, _tasks) = std::sync::mpsc::channel();
+        let (buffer_sender, _buffers) = std::sync::mpsc::channel();
+        let manager = ChunkManager::new(task_sender, buffer_sender);
+        let neighbor = [0, 0, 1];
+        manager.chunks.write().unwrap().insert(neighbor, Arc::new(Chunk::empty(neighbor)));
+
+        assert!(manager.set_block([5, 5, 20], BlockType::Stone));
+        manager.stash_modified();
+        assert!(manager.store.contains(neighbor));
+
+        let mut chunks = manager.chunks.write().unwrap();
+        let changed = ChunkManager::place_features(&mut Chunk::empty([0, 0, 0]), vec![leaves_at([2, 2, 16])], &mut chunks, &manager.pending_edits, &manager.modified);
+        assert!(changed.is_empty());
+        assert_eq!(chunks[&neighbor].get(2, 2, 0), BlockType::Air);
+    }
+    #[test]
+    fn autosave_only_stashes_chunks_edited_since_the_last_one() {
+        let (task_sender, _tasks) = std::sync::mpsc::channel();
+        let (buffer_sender, _buffers) = std::sync::mpsc::channel();
+        let manager = ChunkManager::new(task_sender, buffer_sender);
+        let (edited, restored) = ([0, 0, 0], [1, 0, 0]);
+        for origin in [edited, restored] {
+            manager.chunks.write().unwrap().insert(origin, Arc::new(Chunk::empty(origin)));
         }
-        println!("get_buffers is unlocking chunks");
-        (vertices, indices)
+        // what the chunk worker does with a chunk that came out of the store
+        manager.modified.lock().unwrap().insert(restored);
+
+        manager.set_block([1, 1, 1], BlockType::Stone);
+        manager.stash_modified();
+        assert!(manager.store.contains(edited));
+        assert!(!manager.store.contains(restored));
+        assert!(manager.unsaved.lock().unwrap().is_empty());
+        // both are still protected from spill
+        assert_eq!(*manager.modified.lock().unwrap(), HashSet::from([edited, restored]));
+
+        // nothing new to stash until the next edit
+        let stored = manager.store.load(edited).unwrap();
+        manager.chunks.write().unwrap().insert(edited, Arc::new(Chunk::from_fn(edited, |_, _, _| BlockType::Dirt)));
+        manager.stash_modified();
+        assert_eq!(manager.store.load(edited).unwrap().to_bytes(), stored.to_bytes());
     }
-}
\ No newline at end of file
+    #[test]
+    fn unloading_saves_only_unsaved_edits() {
+        let (task_sender, _tasks) = std::sync::mpsc::channel();
+        let (buffer_sender, _buffers) = std::sync::mpsc::channel();
+        let mut manager = ChunkManager::new(task_sender, buffer_sender);
+        let (edited, restored) = ([20, 0, 0], [21, 0, 0]);
+        for origin in [edited, restored] {
+            manager.chunks.write().unwrap().insert(origin, Arc::new(Chunk::empty(origin)));
+        }
+        manager.modified.lock().unwrap().insert(restored);
+        manager.set_block([20 * 16, 1, 1], BlockType::Stone);
+
+        // both are far out of range of the origin
+        manager.update_chunks(Vec3::new(0.0, 0.0, 0.0), Arc::new(RwLock::new(HashMap::new())));
+        assert!(manager.store.contains(edited));
+        assert!(!manager.store.contains(restored));
+        assert!(manager.modified.lock().unwrap().is_empty());
+        assert!(manager.unsaved.lock().unwrap().is_empty());
+    }
+}
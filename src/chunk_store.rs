@@ -0,0 +1,82 @@
+use std::{collections::HashMap, io, sync::Mutex};
+
+use crate::{chunk::Chunk, world_save::WorldSave};
+
+/// Chunks the player changed that have since been unloaded. They are kept serialized and handed
+/// back instead of regenerating, so edits survive walking away and coming back. With a world save
+/// behind it, `flush` moves them to disk and chunks saved in earlier sessions are found there too.
+#[derive(Default)]
+pub struct ChunkStore {
+    chunks: Mutex<HashMap<[i32; 3], Vec<u8>>>,
+    save: Option<WorldSave>,
+    // region files are read, patched and rewritten, two flushes at once could lose each other's chunks
+    flush_lock: Mutex<()>,
+}
+
+impl ChunkStore {
+    pub fn new() -> Self {
+        ChunkStore::default()
+    }
+
+    pub fn with_save(save: WorldSave) -> Self {
+        ChunkStore { save: Some(save), ..ChunkStore::default() }
+    }
+
+    pub fn world_save(&self) -> Option<&WorldSave> {
+        self.save.as_ref()
+    }
+
+    // replaces whatever was stored for this origin
+    pub fn save(&self, chunk: &Chunk) {
+        self.chunks.lock().unwrap().insert(chunk.origin, chunk.to_bytes());
+    }
+
+    // the stored copy stays put, so a restored chunk the player didn't touch isn't saved again when
+    // it unloads
+    pub fn load(&self, origin: [i32; 3]) -> Option<Chunk> {
+        if let Some(bytes) = self.chunks.lock().unwrap().get(&origin) {
+            return Chunk::from_bytes(bytes);
+        }
+
+        match self.save.as_ref()?.load_chunk(origin) {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                // regenerating is the best we can do, but say so
+                println!("Failed to load saved chunk {:?}: {}", origin, e);
+                None
+            }
+        }
+    }
+
+    pub fn contains(&self, origin: [i32; 3]) -> bool {
+        self.chunks.lock().unwrap().contains_key(&origin)
+    }
+
+    // chunks waiting in memory, not counting what is already on disk
+    pub fn len(&self) -> usize {
+        self.chunks.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // writes the in-memory chunks to the world save and forgets them, they load from disk from now on.
+    // without a save there is nowhere to put them and they stay. returns how many were written.
+    // the store isn't locked during the write, so saving from the render thread never waits on disk
+    pub fn flush(&self) -> io::Result<usize> {
+        let Some(save) = &self.save else {
+            return Ok(0);
+        };
+        let _flushing = self.flush_lock.lock().unwrap();
+
+        let snapshot = self.chunks.lock().unwrap().clone();
+        let chunks: Vec<Chunk> = snapshot.values().filter_map(|bytes| Chunk::from_bytes(bytes)).collect();
+        save.save_chunks(&chunks)?;
+
+        // anything saved again meanwhile is newer than what we wrote and has to stay for the next flush
+        let mut stored = self.chunks.lock().unwrap();
+        stored.retain(|origin, bytes| snapshot.get(origin) != Some(bytes));
+        Ok(chunks.len())
+    }
+}
@@ -0,0 +1,120 @@
+use crate::{block::BlockType, chunk::Chunk, rng::ChunkRng, terrain::WorldSeed};
+
+/// A block placed by a feature, in world coordinates. Features that spill over a chunk border
+/// are queued by the chunk manager until the chunk they land in gets generated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PendingEdit {
+    pub pos: [i32; 3],
+    pub block_type: BlockType,
+}
+
+impl PendingEdit {
+    pub fn chunk_origin(&self) -> [i32; 3] {
+        [self.pos[0].div_euclid(16), self.pos[1].div_euclid(16), self.pos[2].div_euclid(16)]
+    }
+
+    // whether this edit wins over another one queued for the same position
+    pub fn overrides(&self, queued: BlockType) -> bool {
+        self.block_type == BlockType::Log && queued == BlockType::Leaves
+    }
+
+    // writes the block into `chunk` unless something solid is already there.
+    // the chunk has to be the one returned by `chunk_origin`
+    pub fn apply(&self, chunk: &mut Chunk) {
+        let [x, y, z] = self.pos.map(|coord| coord.rem_euclid(16) as usize);
+        let existing = chunk.get(x, y, z);
+
+        let replaceable = matches!(existing, BlockType::Air | BlockType::TallGrass | BlockType::Flower)
+            // trunks push through the leaves of neighbouring trees
+            || (existing == BlockType::Leaves && self.block_type == BlockType::Log);
+        if replaceable {
+            chunk.set(x, y, z, self.block_type);
+        }
+    }
+}
+
+/// Places trees, tall grass and flowers on grass surfaces.
+pub struct VegetationPass {
+    pub seed: WorldSeed,
+    // chance per grass column
+    pub tree_chance: f64,
+    pub tall_grass_chance: f64,
+    pub flower_chance: f64,
+}
+
+impl VegetationPass {
+    pub fn new(seed: WorldSeed) -> Self {
+        VegetationPass { seed, tree_chance: 0.008, tall_grass_chance: 0.12, flower_chance: 0.02 }
+    }
+
+    // decorates `chunk` and returns the edits that fall outside of it
+    pub fn decorate(&self, chunk: &mut Chunk) -> Vec<PendingEdit> {
+        let mut rng = ChunkRng::new(self.seed, chunk.origin, 0x7ee);
+        let world_origin = [chunk.origin[0] * 16, chunk.origin[1] * 16, chunk.origin[2] * 16];
+        let mut spill = Vec::new();
+
+        for x in 0..16 {
+            for z in 0..16 {
+                // the surface is the top grass block with air on it. a grass block on the chunk's
+                // top layer can't be checked, so it is never decorated (in any chunk)
+                let surface = (0..15).rev().find(|&y| {
+                    chunk.get(x, y, z) == BlockType::Grass && chunk.get(x, y + 1, z) == BlockType::Air
+                });
+                let Some(y) = surface else {
+                    // keep the random stream in step regardless of the terrain
+                    rng.next_u64();
+                    continue;
+                };
+
+                let roll = rng.next_f64();
+                let ground = [world_origin[0] + x as i32, world_origin[1] + y as i32, world_origin[2] + z as i32];
+
+                if roll < self.tree_chance {
+                    let trunk_height = rng.range(4, 7);
+                    for edit in Self::tree(ground, trunk_height) {
+                        if edit.chunk_origin() == chunk.origin {
+                            edit.apply(chunk);
+                        } else {
+                            spill.push(edit);
+                        }
+                    }
+                } else if roll < self.tree_chance + self.tall_grass_chance {
+                    chunk.set(x, y + 1, z, BlockType::TallGrass);
+                } else if roll < self.tree_chance + self.tall_grass_chance + self.flower_chance {
+                    chunk.set(x, y + 1, z, BlockType::Flower);
+                }
+            }
+        }
+
+        spill
+    }
+
+    // every block of a tree standing on `ground`, trunk first so it wins over the leaves
+    fn tree(ground: [i32; 3], trunk_height: i32) -> Vec<PendingEdit> {
+        let mut edits = Vec::new();
+        let top = ground[1] + trunk_height;
+
+        for y in ground[1] + 1..=top {
+            edits.push(PendingEdit { pos: [ground[0], y, ground[2]], block_type: BlockType::Log });
+        }
+
+        // two wide layers around the top of the trunk, then two narrow ones on top
+        for y in top - 2..=top + 1 {
+            let radius: i32 = if y < top { 2 } else { 1 };
+            for dx in -radius..=radius {
+                for dz in -radius..=radius {
+                    // round off the corners
+                    if radius > 1 && dx.abs() == radius && dz.abs() == radius {
+                        continue;
+                    }
+                    if y <= top && dx == 0 && dz == 0 {
+                        continue;
+                    }
+                    edits.push(PendingEdit { pos: [ground[0] + dx, y, ground[2] + dz], block_type: BlockType::Leaves });
+                }
+            }
+        }
+
+        edits
+    }
+}
@@ -0,0 +1,24 @@
+#[macro_use]
+extern crate glium;
+
+pub mod anvil;
+pub mod biome;
+pub mod block;
+pub mod caves;
+pub mod chunk;
+pub mod chunk_buffers;
+pub mod chunk_manager;
+pub mod chunk_store;
+pub mod decoration;
+pub mod map;
+pub mod mesher;
+pub mod nbt;
+pub mod ores;
+pub mod player;
+pub mod raycast;
+pub mod rng;
+pub mod schematic;
+pub mod terrain;
+#[cfg(test)]
+mod test_util;
+pub mod world_save;
@@ -1,19 +1,50 @@
 #[macro_use]
 extern crate glium;
-use std::{collections::{HashMap, HashSet}, sync::{mpsc::{self, channel}, Arc, Mutex, RwLock}, thread, time::Instant};
+use std::{collections::{HashMap, HashSet}, sync::{mpsc::{self, channel}, Arc, Mutex, RwLock}, thread, time::{Duration, Instant}};
 
-use chunk::Chunk;
-use glium::{winit::{event::{ElementState, Event, WindowEvent}, keyboard::{KeyCode, PhysicalKey}}, IndexBuffer, Surface, VertexBuffer};
+use glium::{backend::Facade, winit::{event::{ElementState, Event, MouseButton, WindowEvent}, keyboard::{KeyCode, PhysicalKey}}, IndexBuffer, Surface, VertexBuffer};
 use nalgebra_glm::{self, Vec3};
-mod block;
-use block::{Block, Vertex};
-mod player;
-use player::Player;
-mod chunk_manager;
-mod chunk;
-use chunk_manager::{ChunkManager, ChunkMeshData, WorkerMessage};
+use snake::{
+    block::{Block, Vertex},
+    chunk::Chunk,
+    chunk_buffers::ChunkBufferCache,
+    chunk_manager::{BufferTask, ChunkManager, ChunkMeshData, MeshBuffers, SaveTask, WorkerMessage},
+    chunk_store::ChunkStore,
+    decoration::PendingEdit,
+    mesher::{BorderFaces, Mesher},
+    player::Player,
+    terrain::{generator_from_name, TerrainGenerator, WorldSeed},
+    world_save::{LevelData, WorldSave},
+};
 use threadpool::ThreadPool;
 
+// GPU copy of one chunk's mesh, either half is None when it has no faces
+struct ChunkGpuMesh {
+    opaque: Option<(VertexBuffer<Vertex>, IndexBuffer<u32>)>,
+    translucent: Option<(VertexBuffer<Vertex>, IndexBuffer<u32>)>,
+}
+
+fn upload_mesh<F: Facade>(display: &F, mesh: &MeshBuffers) -> Option<(VertexBuffer<Vertex>, IndexBuffer<u32>)> {
+    if mesh.vertices.is_empty() {
+        return None;
+    }
+    let vertices = VertexBuffer::new(display, &mesh.vertices).unwrap();
+    let indices = IndexBuffer::new(display, glium::index::PrimitiveType::TrianglesList, &mesh.indices).unwrap();
+    Some((vertices, indices))
+}
+
+// how often modified chunks and the player state are written out while playing
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+// a couple of blocks above the ground so the camera doesn't start buried, worlds without
+// any ground get a fixed height
+fn safe_spawn(generator: &dyn TerrainGenerator, x: i32, z: i32) -> [f32; 3] {
+    match generator.surface_height(x, z) {
+        Some(height) => [x as f32, height as f32 + 2.0, z as f32],
+        None => [x as f32, 30.0, z as f32],
+    }
+}
+
 fn main() {
 
     let mut delta_time: f32 = 0.0;
@@ -32,7 +63,8 @@ fn main() {
 
     let dirt_texture = glium::texture::SrgbTexture2d::new(&display, dirt_image).unwrap();
 
-    let dirt_texture = dirt_texture.sampled().magnify_filter(glium::uniforms::MagnifySamplerFilter::Nearest).minify_filter(glium::uniforms::MinifySamplerFilter::Nearest);
+    // repeat so the texture tiles across merged faces
+    let dirt_texture = dirt_texture.sampled().magnify_filter(glium::uniforms::MagnifySamplerFilter::Nearest).minify_filter(glium::uniforms::MinifySamplerFilter::Nearest).wrap_function(glium::uniforms::SamplerWrapFunction::Repeat);
 
     let draw_parameters = glium::DrawParameters {
         depth: glium::Depth {
@@ -43,6 +75,17 @@ fn main() {
         ..Default::default()
     };
 
+    // water is blended over the opaque world and must not hide what is behind it
+    let translucent_draw_parameters = glium::DrawParameters {
+        depth: glium::Depth {
+            test: glium::draw_parameters::DepthTest::IfLess,
+            write: false,
+            ..Default::default()
+        },
+        blend: glium::Blend::alpha_blending(),
+        ..Default::default()
+    };
+
     let mut keys_pressed = HashSet::new();
 
 
@@ -98,25 +141,77 @@ fn main() {
         }
     "#;
 
+    // flat coloured, see-through version of the fragment shader for water
+    let water_fragment_shader_src = r#"
+        #version 150
+        in vec2 v_tex_coords;
+        in vec3 v_normal;
+        in vec3 v_position;
+        
+        out vec4 color;
+        
+        void main() {
+            vec3 light_dir = normalize(vec3(-0.2, -1.0, -0.3));
+            vec3 norm = normalize(v_normal);
+            float light = 0.5 + 0.5 * max(dot(norm, -light_dir), 0.0);
+            color = vec4(light * vec3(0.2, 0.4, 0.85), 0.6);
+        }
+    "#;
+
     let (task_sender, task_receiver) = mpsc::channel::<WorkerMessage>();
     let (result_sender, result_receiver) = mpsc::channel::<WorkerMessage>();
 
 
     let program = glium::Program::from_source(&display, vertex_shader_src, fragment_shader_src, None).unwrap();
+    let water_program = glium::Program::from_source(&display, vertex_shader_src, water_fragment_shader_src, None).unwrap();
+
+    // saved chunks and the level file live here
+    let world_dir = std::env::args().nth(4).unwrap_or_else(|| "world".to_string());
+    let world_save = WorldSave::open(&world_dir).expect("Failed to open world directory");
+    // C copies the selection here and V pastes it, any .schem file put there can be pasted
+    let clipboard = world_save.dir().join("schematics").join("clipboard.schem");
+
+    // a saved world keeps its own seed and generator, the arguments only pick them for new worlds
+    let saved_level = world_save.load_level().expect("Failed to read level file");
+    let new_world = saved_level.is_none();
+    let mut level = saved_level.unwrap_or_else(|| LevelData {
+        seed: WorldSeed::from_env().0,
+        generator: std::env::args().nth(2).unwrap_or_else(|| "default".to_string()),
+        spawn: [0.0, 30.0, 0.0],
+        player: None,
+    });
+
+    let seed = WorldSeed(level.seed);
+    println!("World seed: {}", seed.0);
+
+    let generator_name = level.generator.clone();
+    let generator = generator_from_name(&generator_name, seed)
+        .unwrap_or_else(|| panic!("Unknown terrain generator '{}'", generator_name));
 
-    let mut player = Player::new(Vec3::new(0.0, 30.0, 0.0));
+    if new_world {
+        level.spawn = safe_spawn(generator.as_ref(), 0, 0);
+        world_save.save_level(&level).expect("Failed to write level file");
+    } else {
+        println!("Loaded world from {}", world_dir);
+    }
+
+    let mut player = Player::new(Vec3::from(level.spawn));
+    if let Some(state) = &level.player {
+        player.restore_state(state);
+    }
+    let mut last_autosave = Instant::now();
 
-    // i need to initialize these somehow
-    let mut vertex_buffer: Option<VertexBuffer<Vertex>> = None;
-    let mut index_buffer: Option<IndexBuffer<u32>> = None;
+    // one set of GPU buffers per chunk, only changed chunks get re-uploaded
+    let mut chunk_buffers: ChunkBufferCache<ChunkGpuMesh> = ChunkBufferCache::default();
 
     let (buffer_task_sender, buffer_task_receiver) = mpsc::channel::<BufferTask>();
-    let (buffer_result_sender, buffer_result_receiver) = mpsc::channel::<(Vec<Vertex>, Vec<u32>)>();
+    let (buffer_result_sender, buffer_result_receiver) = mpsc::channel::<HashMap<[i32; 3], Arc<ChunkMeshData>>>();
     
     // Wrap chunk_manager in Arc<Mutex>
-    let mut chunk_manager = ChunkManager::new(task_sender);
+    let mut chunk_manager = ChunkManager::new(task_sender, buffer_task_sender.clone());
+    chunk_manager.store = Arc::new(ChunkStore::with_save(world_save));
 
-    let mesh_map: Arc<RwLock<HashMap<[i32; 3], ChunkMeshData>>> = Arc::new(RwLock::new(HashMap::new()));
+    let mesh_map: Arc<RwLock<HashMap<[i32; 3], Arc<ChunkMeshData>>>> = Arc::new(RwLock::new(HashMap::new()));
 
     let mesh_map_clone = mesh_map.clone();
     let mut last_chunk_pos: [i32; 3] = player.chunk_pos;
@@ -124,20 +219,37 @@ fn main() {
     let mut last_chunk_count = 0;
     let mut initial_chunks_requested = false;
 
-    enum BufferTask {
-        // pass a PIT clone of the chunk map and the mesh map
-        UpdateBuffers(HashMap<[i32; 3], Chunk>, Arc<RwLock<HashMap<[i32; 3], ChunkMeshData>>>),
-        Shutdown,
-    }
 
+    let mesher_name = std::env::args().nth(3).unwrap_or_else(|| "greedy".to_string());
+    let mesher = Mesher::from_name(&mesher_name)
+        .unwrap_or_else(|| panic!("Unknown mesher '{}'", mesher_name));
+
+    let buffer_chunk_map = chunk_manager.chunks.clone();
+    let buffer_loaded_area = chunk_manager.loaded_area.clone();
     let buffer_worker = thread::spawn(move || {
         loop {
             match buffer_task_receiver.recv() {
-                Ok(BufferTask::UpdateBuffers(chunk_map, mesh_map)) => {
+                Ok(BufferTask::UpdateBuffers(mut dirty)) => {
                     println!("Buffer worker received update buffers");
-                    let (vertices, indices) = ChunkManager::get_buffers(chunk_map, mesh_map.clone());
-                    buffer_result_sender.send((vertices, indices)).unwrap();
+                    // fold in whatever else queued up meanwhile so a burst of chunks is meshed once
+                    let mut shutdown = false;
+                    for task in buffer_task_receiver.try_iter() {
+                        match task {
+                            BufferTask::UpdateBuffers(more) => dirty.extend(more),
+                            BufferTask::Shutdown => shutdown = true,
+                        }
+                    }
+
+                    // shallow copy, just the Arcs, so the chunk worker isn't locked out while we mesh
+                    let chunks = buffer_chunk_map.read().unwrap().clone();
+                    let area = *buffer_loaded_area.read().unwrap();
+                    let meshes = ChunkManager::get_buffers(&chunks, &dirty, mesh_map.clone(), mesher, BorderFaces::default(), area);
+                    buffer_result_sender.send(meshes).unwrap();
                     println!("Buffer worker Unlocked chunk manager");
+                    if shutdown {
+                        println!("Buffer worker shutting down!");
+                        break;
+                    }
                 }
                 Ok(BufferTask::Shutdown) => {
                     println!("Buffer worker shutting down!");
@@ -148,12 +260,37 @@ fn main() {
         }
     });
 
+    let (save_task_sender, save_task_receiver) = mpsc::channel::<SaveTask>();
+    let save_store = chunk_manager.store.clone();
+    let mut save_worker = Some(thread::spawn(move || {
+        for task in save_task_receiver {
+            match task {
+                SaveTask::Autosave(level) => {
+                    match save_store.flush() {
+                        Ok(written) => println!("Saved {} chunks", written),
+                        Err(e) => println!("Failed to save chunks: {}", e),
+                    }
+                    if let Some(save) = save_store.world_save() {
+                        if let Err(e) = save.save_level(&level) {
+                            println!("Failed to save level: {}", e);
+                        }
+                    }
+                }
+                SaveTask::Shutdown => {
+                    println!("Save worker shutting down!");
+                    break;
+                }
+            }
+        }
+    }));
+
     let pool = ThreadPool::new(8);
    
     let worker = thread::spawn(move || {
         loop {
             match task_receiver.recv() {
                 Ok(WorkerMessage::LoadChunkTask(task)) => {
+                    generator.unload_outside(task.center, task.render_distance);
 
                     let mut chunks_to_process: Vec<[i32; 3]> = Vec::new();
 
@@ -173,23 +310,44 @@ fn main() {
                     }
 
 
-                        let (tx, rx) = channel::<Arc<Chunk>>();
+                        // the flag says the chunk came out of the store rather than the generator
+                        let (tx, rx) = channel::<(Chunk, Vec<PendingEdit>, bool)>();
                         let tx = tx.clone();
                         for origin in chunks_to_process {
                             let tx = tx.clone();
+                            let generator = generator.clone();
+                            let store = task.store.clone();
                             pool.execute(move ||  {
-                                let chunk = Chunk::new(origin);
-                                tx.send(Arc::new(chunk)).expect("Failed to send chunk");
+                                if let Some(chunk) = store.load(origin) {
+                                    tx.send((chunk, Vec::new(), true)).expect("Failed to send chunk");
+                                    return;
+                                }
+                                let mut chunk = generator.generate(origin);
+                                let spill = generator.decorate(&mut chunk);
+                                tx.send((chunk, spill, false)).expect("Failed to send chunk");
                             });
                         }
                         drop(tx);
 
                         for i in 0..num_jobs {
                             match rx.recv() {
-                                Ok(chunk) => {
+                                Ok((mut chunk, spill, restored)) => {
                                     let mut map = task.chunk_map.write().unwrap();
-                                    map.insert(chunk.origin, (*chunk).clone());
-                                    buffer_task_sender.send(BufferTask::UpdateBuffers(map.clone(), mesh_map.clone())).unwrap()
+                                    // neighbours that got features from this chunk need remeshing too.
+                                    // a stored chunk already has its queued features, applying them again
+                                    // could put back leaves the player broke
+                                    let mut dirty = if restored {
+                                        task.modified.lock().unwrap().insert(chunk.origin);
+                                        HashSet::new()
+                                    } else {
+                                        ChunkManager::place_features(&mut chunk, spill, &mut map, &task.pending_edits, &task.modified)
+                                    };
+                                    dirty.insert(chunk.origin);
+                                    // loaded neighbours can now hide the faces they show towards this chunk
+                                    dirty.extend(ChunkManager::face_neighbors(chunk.origin).into_iter().filter(|neighbor| map.contains_key(neighbor)));
+                                    map.insert(chunk.origin, Arc::new(chunk));
+                                    drop(map);
+                                    buffer_task_sender.send(BufferTask::UpdateBuffers(dirty)).unwrap()
                                 }
                                 Err(e) => println!("Failed to receive chunk #{}: {:?}", i+1, e),
                             }
@@ -241,7 +399,15 @@ fn main() {
                     );
 
 
-                    if ((vertex_buffer.is_none() || index_buffer.is_none()) && !initial_chunks_requested) || (do_chunk_updates && player.chunk_pos != last_chunk_pos) {
+                    if last_autosave.elapsed() >= AUTOSAVE_INTERVAL {
+                        last_autosave = Instant::now();
+                        // only copies chunks here, the save worker does the writing
+                        chunk_manager.stash_modified();
+                        level.player = Some(player.state());
+                        save_task_sender.send(SaveTask::Autosave(level.clone())).unwrap();
+                    }
+
+                    if !initial_chunks_requested || (do_chunk_updates && player.chunk_pos != last_chunk_pos) {
                        //println!("calling update chunks");
                        last_chunk_pos = player.chunk_pos;
                        println!("RedrawRequested is trying to lock chunk manager");
@@ -250,16 +416,13 @@ fn main() {
 
                     }
 
-                    // look for buffer updates?
-                    if let Ok(buffer_result) = buffer_result_receiver.try_recv() {
-                        if buffer_result.0.len() > 0 {
-                            vertex_buffer = Some(glium::VertexBuffer::new(&display, &buffer_result.0).unwrap());
-                            index_buffer = Some(glium::IndexBuffer::new(
-                                &display,
-                                glium::index::PrimitiveType::TrianglesList,
-                                &buffer_result.1
-                            ).unwrap());
-                        }
+                    // look for buffer updates? every result covers all loaded chunks, so only the newest matters
+                    if let Some(meshes) = buffer_result_receiver.try_iter().last() {
+                        let plan = chunk_buffers.sync(&meshes, |mesh| ChunkGpuMesh {
+                            opaque: upload_mesh(&display, &mesh.opaque),
+                            translucent: upload_mesh(&display, &mesh.translucent),
+                        });
+                        println!("Uploaded {} chunk meshes, freed {}", plan.upload.len(), plan.free.len());
                     }
 
                     let mut target = display.draw();
@@ -280,26 +443,50 @@ fn main() {
                     // projection: [[f32; 4]; 4] = nalgebra_glm::Mat4::identity().into();
                     target.clear_color_and_depth((120.0/255.0, 167.0/255.0, 255.0/255.0, 1.0), 1.0);
 
-                    if vertex_buffer.is_some() && index_buffer.is_some() {
+                    for (vertices, indices) in chunk_buffers.buffers().filter_map(|mesh| mesh.opaque.as_ref()) {
                         target.draw(
-                            vertex_buffer.as_ref().unwrap(),
-                        index_buffer.as_ref().unwrap(),
-                        &program,
-                        &uniform! {
-                            model: model,
-                            view: view,
-                            projection: projection,
-                            tex: dirt_texture,
-                        },
+                            vertices,
+                            indices,
+                            &program,
+                            &uniform! {
+                                model: model,
+                                view: view,
+                                projection: projection,
+                                tex: dirt_texture,
+                            },
                             &draw_parameters)
                             .unwrap();
+                    }
 
+                    // translucent geometry goes last so the opaque world shows through it
+                    for (vertices, indices) in chunk_buffers.buffers().filter_map(|mesh| mesh.translucent.as_ref()) {
+                        target.draw(
+                            vertices,
+                            indices,
+                            &water_program,
+                            &uniform! {
+                                model: model,
+                                view: view,
+                                projection: projection,
+                            },
+                            &translucent_draw_parameters)
+                            .unwrap();
                     }
                     target.finish().unwrap();
                 },
                 WindowEvent::KeyboardInput {  device_id, event, is_synthetic  } => {
                     match event.state {
                         ElementState::Pressed => {
+                            if !event.repeat {
+                                player.handle_key_pressed(event.physical_key);
+                                match event.physical_key {
+                                    PhysicalKey::Code(KeyCode::KeyZ) => player.mark_corner(0, &chunk_manager),
+                                    PhysicalKey::Code(KeyCode::KeyX) => player.mark_corner(1, &chunk_manager),
+                                    PhysicalKey::Code(KeyCode::KeyC) => player.copy_selection(&chunk_manager, &clipboard),
+                                    PhysicalKey::Code(KeyCode::KeyV) => player.paste_schematic(&chunk_manager, &clipboard),
+                                    _ => (),
+                                }
+                            }
                             keys_pressed.insert(event.physical_key);
                         }
                         ,
@@ -308,6 +495,14 @@ fn main() {
                         },
                     }
                 },
+                WindowEvent::MouseInput { state: ElementState::Pressed, button, .. } => {
+                    // the edit marks the chunk and any bordering neighbours dirty, the mesh worker picks it up
+                    match button {
+                        MouseButton::Left => { player.break_block(&chunk_manager); },
+                        MouseButton::Right => { player.place_block(&chunk_manager); },
+                        _ => (),
+                    }
+                }
                 WindowEvent::CursorMoved {position, ..} => {
                     
                     let xpos = position.x as f32;
@@ -325,6 +520,17 @@ fn main() {
             glium::winit::event::Event::AboutToWait => {
                 window.request_redraw();
             }
+            // covers both closing the window and escape
+            glium::winit::event::Event::LoopExiting => {
+                // the last save goes through the worker too, so it can't race an autosave in progress
+                chunk_manager.stash_modified();
+                level.player = Some(player.state());
+                save_task_sender.send(SaveTask::Autosave(level.clone())).unwrap();
+                save_task_sender.send(SaveTask::Shutdown).unwrap();
+                if let Some(save_worker) = save_worker.take() {
+                    save_worker.join().unwrap();
+                }
+            }
             _ => (),
         }
     });
@@ -0,0 +1,117 @@
+use std::{collections::HashMap, sync::Arc};
+
+use image::{Rgba, RgbaImage};
+
+use crate::{block::BlockType, chunk::Chunk};
+
+// colour of a block seen from above
+pub fn block_color(block_type: BlockType) -> [u8; 3] {
+    match block_type {
+        BlockType::Air => [0, 0, 0],
+        BlockType::TNT => [200, 40, 40],
+        BlockType::Grass => [95, 159, 53],
+        BlockType::Dirt => [134, 96, 67],
+        BlockType::Stone => [125, 125, 125],
+        BlockType::Bedrock => [60, 60, 60],
+        BlockType::Sand => [219, 207, 163],
+        BlockType::Snow => [245, 250, 250],
+        BlockType::Water => [50, 90, 200],
+        BlockType::CoalOre => [50, 50, 50],
+        BlockType::IronOre => [190, 160, 140],
+        BlockType::GoldOre => [240, 210, 70],
+        BlockType::DiamondOre => [100, 220, 220],
+        BlockType::Log => [102, 81, 51],
+        BlockType::Leaves => [60, 120, 40],
+        BlockType::TallGrass => [110, 170, 60],
+        BlockType::Flower => [220, 60, 90],
+    }
+}
+
+/// Top-down map of the chunk columns between `min` and `max` (inclusive, as [x, z] chunk
+/// coordinates, in either order). One pixel per block column, x to the right and z downwards,
+/// coloured by the highest non-air block and shaded by its height. Columns with nothing loaded
+/// are transparent.
+pub fn render_map(chunks: &HashMap<[i32; 3], Arc<Chunk>>, min: [i32; 2], max: [i32; 2]) -> RgbaImage {
+    let (min, max) = ([min[0].min(max[0]), min[1].min(max[1])], [min[0].max(max[0]), min[1].max(max[1])]);
+    let width = ((max[0] - min[0] + 1) * 16) as u32;
+    let depth = ((max[1] - min[1] + 1) * 16) as u32;
+
+    // every loaded chunk y per column, top first, so the scan can stop at the first hit
+    let mut stacks: HashMap<[i32; 2], Vec<i32>> = HashMap::new();
+    for origin in chunks.keys() {
+        if origin[0] >= min[0] && origin[0] <= max[0] && origin[2] >= min[1] && origin[2] <= max[1] {
+            stacks.entry([origin[0], origin[2]]).or_default().push(origin[1]);
+        }
+    }
+    for stack in stacks.values_mut() {
+        stack.sort_unstable_by(|a, b| b.cmp(a));
+    }
+
+    let mut tops: HashMap<[u32; 2], (i32, BlockType)> = HashMap::new();
+    for (column, stack) in &stacks {
+        for x in 0..16 {
+            for z in 0..16 {
+                let top = stack.iter().find_map(|&chunk_y| {
+                    let chunk = &chunks[&[column[0], chunk_y, column[1]]];
+                    (0..16).rev()
+                        .find(|&y| chunk.get(x, y, z) != BlockType::Air)
+                        .map(|y| (chunk_y * 16 + y as i32, chunk.get(x, y, z)))
+                });
+
+                if let Some(top) = top {
+                    let pixel = [
+                        ((column[0] - min[0]) * 16) as u32 + x as u32,
+                        ((column[1] - min[1]) * 16) as u32 + z as u32,
+                    ];
+                    tops.insert(pixel, top);
+                }
+            }
+        }
+    }
+
+    let lowest = tops.values().map(|(height, _)| *height).min().unwrap_or(0);
+    let highest = tops.values().map(|(height, _)| *height).max().unwrap_or(0);
+
+    let mut image = RgbaImage::new(width, depth);
+    for (&[px, pz], &(height, block_type)) in &tops {
+        // low ground is darker, high ground brighter
+        let t = if highest > lowest { (height - lowest) as f32 / (highest - lowest) as f32 } else { 0.5 };
+        let shade = 0.6 + 0.6 * t;
+
+        let [r, g, b] = block_color(block_type).map(|c| (c as f32 * shade).min(255.0) as u8);
+        image.put_pixel(px, pz, Rgba([r, g, b, 255]));
+    }
+
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colours_by_top_block_and_leaves_missing_columns_transparent() {
+        // a stone floor with a single grass pillar at x 3, z 5
+        let chunk = Chunk::from_fn([0, 0, 0], |x, y, z| match (x, y, z) {
+            (3, 0..=10, 5) => BlockType::Grass,
+            (_, 0, _) => BlockType::Stone,
+            _ => BlockType::Air,
+        });
+        let chunks = HashMap::from([([0, 0, 0], Arc::new(chunk))]);
+
+        // corners the wrong way round on x, the chunk at x 1 isn't loaded
+        let image = render_map(&chunks, [1, 0], [0, 0]);
+        assert_eq!(image.dimensions(), (32, 16));
+
+        // the pillar is the highest point so it is brightest, the floor the lowest so darkest
+        let shade = |block_type: BlockType, shade: f32| {
+            let [r, g, b] = block_color(block_type).map(|c| (c as f32 * shade).min(255.0) as u8);
+            Rgba([r, g, b, 255])
+        };
+        assert_eq!(*image.get_pixel(3, 5), shade(BlockType::Grass, 1.2));
+        assert_eq!(*image.get_pixel(0, 0), shade(BlockType::Stone, 0.6));
+        assert_eq!(*image.get_pixel(15, 15), shade(BlockType::Stone, 0.6));
+        assert_eq!(image.get_pixel(16, 0)[3], 0);
+        assert_eq!(image.get_pixel(31, 15)[3], 0);
+    }
+}
@@ -0,0 +1,389 @@
+use std::{collections::HashMap, sync::Arc};
+
+use nalgebra_glm::Vec3;
+use strum::IntoEnumIterator;
+
+use crate::{block::{Block, BlockType, FaceDir}, chunk::Chunk, chunk_manager::{ChunkManager, ChunkMeshData, LoadedArea, MeshBuffers}};
+
+/// How chunk meshes are built.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mesher {
+    // one quad per visible block face
+    Naive,
+    // merges touching faces of the same block type into bigger quads
+    #[default]
+    Greedy,
+}
+
+/// What to do with faces that look into a chunk that isn't loaded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BorderFaces {
+    // always draw them
+    Visible,
+    // for chunks strictly inside the loaded area the missing neighbour is on its way, so treat it as
+    // solid and rely on the remesh when it arrives. chunks on the edge still draw those faces
+    #[default]
+    SolidInsideArea,
+}
+
+impl BorderFaces {
+    pub fn missing_is_solid(&self, origin: [i32; 3], area: Option<LoadedArea>) -> bool {
+        match self {
+            BorderFaces::Visible => false,
+            BorderFaces::SolidInsideArea => area.is_some_and(|area| area.contains_strictly(origin)),
+        }
+    }
+}
+
+impl Mesher {
+    pub fn from_name(name: &str) -> Option<Mesher> {
+        match name {
+            "naive" => Some(Mesher::Naive),
+            "greedy" => Some(Mesher::Greedy),
+            _ => None,
+        }
+    }
+
+    pub fn mesh_chunk(&self, origin: [i32; 3], chunk: &Chunk, chunks: &HashMap<[i32; 3], Arc<Chunk>>, missing_is_solid: bool) -> ChunkMeshData {
+        if chunk.is_empty() {
+            return ChunkMeshData::new(MeshBuffers::default(), MeshBuffers::default());
+        }
+
+        match self {
+            Mesher::Naive => mesh_naive(origin, chunk, chunks, missing_is_solid),
+            Mesher::Greedy => mesh_greedy(origin, chunk, chunks, missing_is_solid),
+        }
+    }
+}
+
+fn mesh_naive(origin: [i32; 3], chunk: &Chunk, chunks: &HashMap<[i32; 3], Arc<Chunk>>, missing_is_solid: bool) -> ChunkMeshData {
+    let mut opaque = MeshBuffers::default();
+    let mut translucent = MeshBuffers::default();
+
+    for x in 0..16 {
+        for y in 0..16 {
+            for z in 0..16 {
+                let block = chunk.block(x, y, z);
+                if block.block_type == BlockType::Air {
+                    continue;
+                }
+
+                // plants are never hidden by their neighbours
+                if block.block_type.is_plant() {
+                    let (block_vertices, block_indices) = block.generate_cross();
+                    opaque.extend(&block_vertices, &block_indices);
+                    continue;
+                }
+
+                let world_pos = [origin[0] * 16 + x as i32, origin[1] * 16 + y as i32, origin[2] * 16 + z as i32];
+
+                // Check each face direction
+                let faces_to_render: Vec<FaceDir> = FaceDir::iter()
+                    .filter(|face| ChunkManager::should_render_face(neighbor_of(world_pos, face), &block, chunks, missing_is_solid))
+                    .collect();
+
+                // Generate only the necessary faces
+                let (block_vertices, block_indices) = block.generate_faces(faces_to_render);
+
+                if block.block_type.is_translucent() {
+                    translucent.extend(&block_vertices, &block_indices);
+                } else {
+                    opaque.extend(&block_vertices, &block_indices);
+                }
+            }
+        }
+    }
+
+    ChunkMeshData::new(opaque, translucent)
+}
+
+// sweeps each face direction one 16x16 slice at a time, marking visible faces in a mask and then
+// growing rectangles of the same block type out of it
+fn mesh_greedy(origin: [i32; 3], chunk: &Chunk, chunks: &HashMap<[i32; 3], Arc<Chunk>>, missing_is_solid: bool) -> ChunkMeshData {
+    let mut opaque = MeshBuffers::default();
+    let mut translucent = MeshBuffers::default();
+
+    // plants aren't faces, they keep their crossed quads
+    for x in 0..16 {
+        for y in 0..16 {
+            for z in 0..16 {
+                if chunk.get(x, y, z).is_plant() {
+                    let block = chunk.block(x, y, z);
+                    let (block_vertices, block_indices) = block.generate_cross();
+                    opaque.extend(&block_vertices, &block_indices);
+                }
+            }
+        }
+    }
+
+    for face in FaceDir::iter() {
+        let (normal, u_axis, v_axis) = face.get_axes();
+        let normal_axis = axis_of(normal);
+        // the two axes spanning the slice, i runs along the rows of the mask and j across them
+        let (i_axis, j_axis) = match normal_axis {
+            0 => (1, 2),
+            1 => (0, 2),
+            _ => (0, 1),
+        };
+
+        for slice in 0..16 {
+            let mut mask: [[Option<BlockType>; 16]; 16] = [[None; 16]; 16];
+
+            for (i, row) in mask.iter_mut().enumerate() {
+                for (j, cell) in row.iter_mut().enumerate() {
+                    let mut local = [0; 3];
+                    local[normal_axis] = slice;
+                    local[i_axis] = i;
+                    local[j_axis] = j;
+
+                    let block = chunk.block(local[0], local[1], local[2]);
+                    if block.block_type == BlockType::Air || block.block_type.is_plant() {
+                        continue;
+                    }
+
+                    let world_pos = [
+                        origin[0] * 16 + local[0] as i32,
+                        origin[1] * 16 + local[1] as i32,
+                        origin[2] * 16 + local[2] as i32,
+                    ];
+                    if ChunkManager::should_render_face(neighbor_of(world_pos, &face), &block, chunks, missing_is_solid) {
+                        *cell = Some(block.block_type);
+                    }
+                }
+            }
+
+            for j in 0..16 {
+                let mut i = 0;
+                while i < 16 {
+                    let Some(block_type) = mask[i][j] else {
+                        i += 1;
+                        continue;
+                    };
+
+                    let mut width = 1;
+                    while i + width < 16 && mask[i + width][j] == Some(block_type) {
+                        width += 1;
+                    }
+
+                    let mut height = 1;
+                    while j + height < 16 && (i..i + width).all(|k| mask[k][j + height] == Some(block_type)) {
+                        height += 1;
+                    }
+
+                    for row in mask.iter_mut().skip(i).take(width) {
+                        for cell in row.iter_mut().skip(j).take(height) {
+                            *cell = None;
+                        }
+                    }
+
+                    // centre of the merged rectangle, pushed out to the face
+                    let mut center = [0.0f32; 3];
+                    center[normal_axis] = slice as f32;
+                    center[i_axis] = i as f32 + (width - 1) as f32 * 0.5;
+                    center[j_axis] = j as f32 + (height - 1) as f32 * 0.5;
+                    let center = Vec3::new(
+                        (origin[0] * 16) as f32 + center[0],
+                        (origin[1] * 16) as f32 + center[1],
+                        (origin[2] * 16) as f32 + center[2],
+                    ) + normal * 0.5;
+
+                    let mut size = [1.0f32; 3];
+                    size[i_axis] = width as f32;
+                    size[j_axis] = height as f32;
+                    let size_u = size[axis_of(u_axis)];
+                    let size_v = size[axis_of(v_axis)];
+
+                    let target = if block_type.is_translucent() { &mut translucent } else { &mut opaque };
+                    let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+                    Block::add_quad(&mut vertices, &mut indices, center, &face, size_u, size_v);
+                    target.extend(&vertices, &indices);
+
+                    i += width;
+                }
+            }
+        }
+    }
+
+    ChunkMeshData::new(opaque, translucent)
+}
+
+// world position of the block a face looks at
+fn neighbor_of(world_pos: [i32; 3], face: &FaceDir) -> [i32; 3] {
+    let (normal, _, _) = face.get_axes();
+    [
+        world_pos[0] + normal.x as i32,
+        world_pos[1] + normal.y as i32,
+        world_pos[2] + normal.z as i32,
+    ]
+}
+
+// index of the world axis a unit vector runs along
+fn axis_of(direction: Vec3) -> usize {
+    if direction.x != 0.0 {
+        0
+    } else if direction.y != 0.0 {
+        1
+    } else {
+        2
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::{rng::ChunkRng, terrain::{HeightmapGenerator, TerrainGenerator, WorldSeed}};
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Eq, Hash)]
+    enum Cell {
+        // normal, plane along it * 2, the other two block coordinates
+        Face([i32; 3], i32, i32, i32),
+        // block and which of the two diagonals
+        Plant([i32; 3], bool),
+    }
+
+    // every unit face and plant quad a mesh covers -> how many quads cover it, so overlaps show up too
+    fn cells(mesh: &MeshBuffers) -> HashMap<Cell, usize> {
+        let mut cells = HashMap::new();
+        for quad in mesh.vertices.chunks(4) {
+            let positions = [quad[0], quad[1], quad[2], quad[3]].map(|vertex| vertex.position());
+            let normal = quad[0].normal().map(|n| n.round() as i32);
+            let axis = (0..3).find(|axis| normal[*axis] != 0).unwrap();
+
+            // plants are lit from above but stand upright, so they don't lie in their normal's plane
+            if positions.iter().any(|position| position[axis] != positions[0][axis]) {
+                let centre = [0, 1, 2].map(|other| (positions.iter().map(|position| position[other]).sum::<f32>() / 4.0).round() as i32);
+                let rising = (positions[1][0] - positions[0][0]) * (positions[1][2] - positions[0][2]) > 0.0;
+                *cells.entry(Cell::Plant(centre, rising)).or_insert(0) += 1;
+                continue;
+            }
+
+            // blocks are centred on whole numbers, so a quad spans from the first one's lower edge
+            // to the last one's upper edge
+            let plane = (positions[0][axis] * 2.0).round() as i32;
+            let [u, v] = [(axis + 1) % 3, (axis + 2) % 3].map(|other| {
+                let low = positions.iter().map(|position| position[other]).fold(f32::MAX, f32::min);
+                let high = positions.iter().map(|position| position[other]).fold(f32::MIN, f32::max);
+                (low + 0.5).round() as i32..=(high - 0.5).round() as i32
+            });
+            for u in u {
+                for v in v.clone() {
+                    *cells.entry(Cell::Face(normal, plane, u, v)).or_insert(0) += 1;
+                }
+            }
+        }
+        cells
+    }
+
+    fn assert_same_coverage(origin: [i32; 3], chunks: &HashMap<[i32; 3], Arc<Chunk>>) {
+        let naive = Mesher::Naive.mesh_chunk(origin, &chunks[&origin], chunks, false);
+        let greedy = Mesher::Greedy.mesh_chunk(origin, &chunks[&origin], chunks, false);
+
+        for (naive, greedy) in [(&naive.opaque, &greedy.opaque), (&naive.translucent, &greedy.translucent)] {
+            let (naive, greedy) = (cells(naive), cells(greedy));
+            assert!(naive.values().all(|count| *count == 1), "chunk {:?}: naive faces overlap", origin);
+            assert_eq!(naive, greedy, "chunk {:?}", origin);
+        }
+        assert!(greedy.opaque.vertices.len() <= naive.opaque.vertices.len());
+    }
+
+    fn solid(origin: [i32; 3]) -> Arc<Chunk> {
+        Arc::new(Chunk::from_fn(origin, |_, _, _| BlockType::Stone))
+    }
+
+    #[test]
+    fn greedy_covers_the_same_faces_as_naive() {
+        // scattered blocks of every kind, nothing to merge
+        let mut rng = ChunkRng::new(WorldSeed(5), [0, 0, 0], 1);
+        let types = [BlockType::Air, BlockType::Air, BlockType::Stone, BlockType::Dirt, BlockType::Water, BlockType::Leaves];
+        let mut noisy = Chunk::empty([0, 0, 0]);
+        for x in 0..16 {
+            for y in 0..16 {
+                for z in 0..16 {
+                    noisy.set(x, y, z, types[(rng.next_u64() % types.len() as u64) as usize]);
+                }
+            }
+        }
+        assert_same_coverage([0, 0, 0], &HashMap::from([([0, 0, 0], Arc::new(noisy))]));
+
+        // a single block
+        let mut single = Chunk::empty([2, 0, 0]);
+        single.set(4, 5, 6, BlockType::Grass);
+        assert_same_coverage([2, 0, 0], &HashMap::from([([2, 0, 0], Arc::new(single))]));
+
+        // real terrain with water and plants, next to its neighbours
+        let generator = HeightmapGenerator::new(WorldSeed(9));
+        let mut chunks = HashMap::new();
+        for x in -1..=1 {
+            for y in 0..=2 {
+                for z in -1..=1 {
+                    let mut chunk = generator.generate([x, y, z]);
+                    generator.decorate(&mut chunk);
+                    chunks.insert([x, y, z], Arc::new(chunk));
+                }
+            }
+        }
+        for origin in [[0, 0, 0], [0, 1, 0], [0, 2, 0]] {
+            assert_same_coverage(origin, &chunks);
+        }
+    }
+
+    #[test]
+    fn solid_block_of_chunks_only_has_faces_on_its_hull() {
+        let mut chunks = HashMap::new();
+        for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    chunks.insert([x, y, z], solid([x, y, z]));
+                }
+            }
+        }
+        // block centres run from -16 to 31 on every axis
+        let (low, high) = (-16.5, 31.5);
+
+        for mesher in [Mesher::Naive, Mesher::Greedy] {
+            let mut hull_area = 0.0;
+            for origin in chunks.keys() {
+                let mesh = mesher.mesh_chunk(*origin, &chunks[origin], &chunks, false);
+                assert!(mesh.translucent.vertices.is_empty());
+
+                for quad in mesh.opaque.vertices.chunks(4) {
+                    let normal = quad[0].normal();
+                    let axis = (0..3).find(|axis| normal[*axis] != 0.0).unwrap();
+                    // facing out of the block, on the face it faces out of
+                    let plane = if normal[axis] > 0.0 { high } else { low };
+                    for vertex in quad {
+                        assert_eq!(vertex.position()[axis], plane, "{:?} chunk {:?} has an inside face", mesher, origin);
+                    }
+                    let [p0, p1, _, p3] = [quad[0], quad[1], quad[2], quad[3]].map(|vertex| Vec3::from(vertex.position()));
+                    hull_area += (p1 - p0).cross(&(p3 - p0)).norm();
+                }
+
+                // with missing neighbours counted as solid there is nothing to see at all
+                let hidden = mesher.mesh_chunk(*origin, &chunks[origin], &chunks, true);
+                assert!(hidden.opaque.vertices.is_empty(), "{:?} chunk {:?}", mesher, origin);
+            }
+            // and the hull is covered completely
+            assert_eq!(hull_area, 6.0 * 48.0 * 48.0, "{:?}", mesher);
+        }
+    }
+
+    #[test]
+    fn missing_neighbours_follow_the_border_policy() {
+        let chunks = HashMap::from([([0, 0, 0], solid([0, 0, 0]))]);
+
+        let hidden = Mesher::Naive.mesh_chunk([0, 0, 0], &chunks[&[0, 0, 0]], &chunks, true);
+        assert!(hidden.opaque.vertices.is_empty());
+
+        // one quad per block on each of the six sides
+        let shown = Mesher::Naive.mesh_chunk([0, 0, 0], &chunks[&[0, 0, 0]], &chunks, false);
+        assert_eq!(shown.opaque.vertices.len(), 6 * 256 * 4);
+
+        let area = LoadedArea { min: [-2, -2, -2], max: [2, 2, 2] };
+        assert!(BorderFaces::SolidInsideArea.missing_is_solid([1, 0, -1], Some(area)));
+        assert!(!BorderFaces::SolidInsideArea.missing_is_solid([2, 0, 0], Some(area)));
+        assert!(!BorderFaces::SolidInsideArea.missing_is_solid([0, 0, 0], None));
+        assert!(!BorderFaces::Visible.missing_is_solid([0, 0, 0], Some(area)));
+    }
+}
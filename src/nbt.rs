@@ -0,0 +1,260 @@
+use std::{collections::HashMap, io};
+
+// nesting deeper than this is treated as corrupt rather than risking the stack
+const MAX_DEPTH: usize = 512;
+
+/// One value of Minecraft's Named Binary Tag format.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(HashMap<String, Tag>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    pub fn get(&self, key: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(fields) => fields.get(key),
+            _ => None,
+        }
+    }
+
+    // any integer tag, files aren't consistent about which width they use
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Tag::Byte(value) => Some(*value as i64),
+            Tag::Short(value) => Some(*value as i64),
+            Tag::Int(value) => Some(*value as i64),
+            Tag::Long(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_i32(&self) -> Option<i32> {
+        self.as_i64().and_then(|value| i32::try_from(value).ok())
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Tag]> {
+        match self {
+            Tag::List(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_long_array(&self) -> Option<&[i64]> {
+        match self {
+            Tag::LongArray(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("nbt: {}", msg))
+}
+
+// big endian cursor over an uncompressed NBT document
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let end = self.pos.checked_add(len).filter(|end| *end <= self.bytes.len()).ok_or_else(|| invalid("unexpected end of data"))?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn i16(&mut self) -> io::Result<i16> {
+        Ok(i16::from_be_bytes(self.array()?))
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        Ok(i32::from_be_bytes(self.array()?))
+    }
+
+    fn i64(&mut self) -> io::Result<i64> {
+        Ok(i64::from_be_bytes(self.array()?))
+    }
+
+    // arrays and lists carry a signed length
+    fn len(&mut self) -> io::Result<usize> {
+        usize::try_from(self.i32()?).map_err(|_| invalid("negative length"))
+    }
+
+    // java writes modified utf-8, close enough to utf-8 for block names
+    fn string(&mut self) -> io::Result<String> {
+        let len = u16::from_be_bytes(self.array()?) as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    fn payload(&mut self, tag_type: u8, depth: usize) -> io::Result<Tag> {
+        if depth > MAX_DEPTH {
+            return Err(invalid("nested too deep"));
+        }
+        Ok(match tag_type {
+            1 => Tag::Byte(self.u8()? as i8),
+            2 => Tag::Short(self.i16()?),
+            3 => Tag::Int(self.i32()?),
+            4 => Tag::Long(self.i64()?),
+            5 => Tag::Float(f32::from_bits(self.i32()? as u32)),
+            6 => Tag::Double(f64::from_bits(self.i64()? as u64)),
+            7 => {
+                let len = self.len()?;
+                Tag::ByteArray(self.take(len)?.iter().map(|byte| *byte as i8).collect())
+            }
+            8 => Tag::String(self.string()?),
+            9 => {
+                let item_type = self.u8()?;
+                let len = self.len()?;
+                // an empty list may claim the end tag as its type
+                if item_type == 0 && len > 0 {
+                    return Err(invalid("list of end tags"));
+                }
+                let mut items = Vec::new();
+                for _ in 0..len {
+                    items.push(self.payload(item_type, depth + 1)?);
+                }
+                Tag::List(items)
+            }
+            10 => {
+                let mut fields = HashMap::new();
+                loop {
+                    let field_type = self.u8()?;
+                    if field_type == 0 {
+                        break;
+                    }
+                    let name = self.string()?;
+                    fields.insert(name, self.payload(field_type, depth + 1)?);
+                }
+                Tag::Compound(fields)
+            }
+            11 => {
+                let len = self.len()?;
+                Tag::IntArray((0..len).map(|_| self.i32()).collect::<io::Result<_>>()?)
+            }
+            12 => {
+                let len = self.len()?;
+                Tag::LongArray((0..len).map(|_| self.i64()).collect::<io::Result<_>>()?)
+            }
+            other => return Err(invalid(&format!("unknown tag type {}", other))),
+        })
+    }
+}
+
+// reads an uncompressed document, which is a single named compound. returns its name and contents
+pub fn read(bytes: &[u8]) -> io::Result<(String, Tag)> {
+    let mut reader = Reader { bytes, pos: 0 };
+    if reader.u8()? != 10 {
+        return Err(invalid("root is not a compound"));
+    }
+    let name = reader.string()?;
+    let root = reader.payload(10, 0)?;
+    Ok((name, root))
+}
+
+fn write_string(out: &mut Vec<u8>, value: &str) {
+    // longer strings can't be represented, block names never get close
+    let bytes = &value.as_bytes()[..value.len().min(u16::MAX as usize)];
+    out.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+    out.extend_from_slice(bytes);
+}
+
+fn type_id(tag: &Tag) -> u8 {
+    match tag {
+        Tag::Byte(_) => 1,
+        Tag::Short(_) => 2,
+        Tag::Int(_) => 3,
+        Tag::Long(_) => 4,
+        Tag::Float(_) => 5,
+        Tag::Double(_) => 6,
+        Tag::ByteArray(_) => 7,
+        Tag::String(_) => 8,
+        Tag::List(_) => 9,
+        Tag::Compound(_) => 10,
+        Tag::IntArray(_) => 11,
+        Tag::LongArray(_) => 12,
+    }
+}
+
+fn write_payload(out: &mut Vec<u8>, tag: &Tag) {
+    match tag {
+        Tag::Byte(value) => out.push(*value as u8),
+        Tag::Short(value) => out.extend_from_slice(&value.to_be_bytes()),
+        Tag::Int(value) => out.extend_from_slice(&value.to_be_bytes()),
+        Tag::Long(value) => out.extend_from_slice(&value.to_be_bytes()),
+        Tag::Float(value) => out.extend_from_slice(&value.to_be_bytes()),
+        Tag::Double(value) => out.extend_from_slice(&value.to_be_bytes()),
+        Tag::ByteArray(values) => {
+            out.extend_from_slice(&(values.len() as i32).to_be_bytes());
+            out.extend(values.iter().map(|value| *value as u8));
+        }
+        Tag::String(value) => write_string(out, value),
+        Tag::List(items) => {
+            // lists are homogeneous, the first item decides the type
+            out.push(items.first().map_or(0, type_id));
+            out.extend_from_slice(&(items.len() as i32).to_be_bytes());
+            for item in items {
+                write_payload(out, item);
+            }
+        }
+        Tag::Compound(fields) => {
+            for (name, field) in fields {
+                out.push(type_id(field));
+                write_string(out, name);
+                write_payload(out, field);
+            }
+            out.push(0);
+        }
+        Tag::IntArray(values) => {
+            out.extend_from_slice(&(values.len() as i32).to_be_bytes());
+            for value in values {
+                out.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+        Tag::LongArray(values) => {
+            out.extend_from_slice(&(values.len() as i32).to_be_bytes());
+            for value in values {
+                out.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+    }
+}
+
+// the other half of `read`, an uncompressed document with `root` as its named compound
+pub fn write(name: &str, root: &Tag) -> Vec<u8> {
+    let mut out = vec![10];
+    write_string(&mut out, name);
+    match root {
+        Tag::Compound(_) => write_payload(&mut out, root),
+        // documents are always compounds, wrap anything else
+        other => write_payload(&mut out, &Tag::Compound(HashMap::from([(String::new(), other.clone())]))),
+    }
+    out
+}
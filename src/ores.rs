@@ -0,0 +1,76 @@
+use crate::{block::BlockType, chunk::Chunk, rng::ChunkRng, terrain::WorldSeed};
+
+/// Where and how often one kind of ore shows up.
+#[derive(Clone, Copy, Debug)]
+pub struct OreConfig {
+    pub block: BlockType,
+    // veins only start between these world y values (inclusive)
+    pub min_y: i32,
+    pub max_y: i32,
+    // average veins per chunk, a fractional part is the chance of one more vein
+    pub veins_per_chunk: f64,
+    // number of blocks a vein tries to place
+    pub vein_size: u32,
+}
+
+pub fn default_ores() -> Vec<OreConfig> {
+    vec![
+        OreConfig { block: BlockType::CoalOre, min_y: -40, max_y: 64, veins_per_chunk: 8.0, vein_size: 10 },
+        OreConfig { block: BlockType::IronOre, min_y: -40, max_y: 32, veins_per_chunk: 6.0, vein_size: 6 },
+        OreConfig { block: BlockType::GoldOre, min_y: -48, max_y: 0, veins_per_chunk: 2.0, vein_size: 6 },
+        OreConfig { block: BlockType::DiamondOre, min_y: -48, max_y: -24, veins_per_chunk: 0.8, vein_size: 4 },
+    ]
+}
+
+/// Decoration pass that scatters ore veins through stone after the terrain is built.
+pub struct OrePass {
+    pub seed: WorldSeed,
+    pub ores: Vec<OreConfig>,
+}
+
+impl OrePass {
+    pub fn new(seed: WorldSeed) -> Self {
+        OrePass { seed, ores: default_ores() }
+    }
+
+    pub fn decorate(&self, chunk: &mut Chunk) {
+        let base_y = chunk.origin[1] * 16;
+
+        for (i, ore) in self.ores.iter().enumerate() {
+            // one stream per ore so tweaking one ore doesn't reshuffle the others
+            let mut rng = ChunkRng::new(self.seed, chunk.origin, 0x0e5 + i as u64);
+
+            // clamp the ore's y range to this chunk
+            let min_y = ore.min_y.max(base_y);
+            let max_y = ore.max_y.min(base_y + 15);
+            if min_y > max_y {
+                continue;
+            }
+
+            let mut veins = ore.veins_per_chunk as u32;
+            if rng.chance(ore.veins_per_chunk.fract()) {
+                veins += 1;
+            }
+
+            for _ in 0..veins {
+                let mut pos = [
+                    rng.range(0, 16),
+                    rng.range(min_y, max_y + 1) - base_y,
+                    rng.range(0, 16),
+                ];
+
+                // random walk, only replacing stone and never leaving the chunk
+                for _ in 0..ore.vein_size {
+                    let [x, y, z] = pos.map(|coord| coord as usize);
+                    if chunk.get(x, y, z) == BlockType::Stone {
+                        chunk.set(x, y, z, ore.block);
+                    }
+
+                    let axis = rng.range(0, 3) as usize;
+                    let step = if rng.chance(0.5) { 1 } else { -1 };
+                    pos[axis] = (pos[axis] + step).clamp(0, 15);
+                }
+            }
+        }
+    }
+}
@@ -1,11 +1,24 @@
 // let's move the camera and movement here for now?
 
-use std::collections::HashSet;
+use std::{collections::{HashMap, HashSet}, path::Path, sync::Arc};
 
 use glium::winit::{event_loop::ActiveEventLoop, keyboard::{KeyCode, PhysicalKey}};
 use nalgebra_glm::{cross, look_at, normalize, Vec3};
 
-use crate::chunk_manager::ChunkManager;
+use crate::{block::BlockType, chunk::Chunk, chunk_manager::ChunkManager, raycast::{raycast, RaycastHit, MAX_REACH}, schematic::Schematic, world_save::PlayerState};
+
+// what the number keys 1-9 select for placing
+pub const HOTBAR: [BlockType; 9] = [
+    BlockType::Dirt,
+    BlockType::Grass,
+    BlockType::Stone,
+    BlockType::Sand,
+    BlockType::Snow,
+    BlockType::Log,
+    BlockType::Leaves,
+    BlockType::TNT,
+    BlockType::Water,
+];
 
 pub struct Player
 {
@@ -19,6 +32,9 @@ pub struct Player
     pub speed: f32,
     pub chunk_pos: [i32; 3],
     pub camera_offset: f32,
+    pub selected_block: BlockType,
+    // corners of the box the copy key saves as a schematic
+    pub selection: [Option<[i32; 3]>; 2],
 }
 
 impl Player 
@@ -34,7 +50,56 @@ impl Player
         let sensitivity = 0.1;
         let speed = 10.0;
         let chunk_pos = ChunkManager::get_chunk_at(position.into());
-        Player { camera_pos, camera_front, camera_up, yaw, pitch, sensitivity, speed, camera_offset, chunk_pos, position }
+        let selected_block = HOTBAR[0];
+        let selection = [None, None];
+        Player { camera_pos, camera_front, camera_up, yaw, pitch, sensitivity, speed, camera_offset, chunk_pos, position, selected_block, selection }
+    }
+
+    // what goes into the level file
+    pub fn state(&self) -> PlayerState {
+        PlayerState {
+            position: self.position.into(),
+            yaw: self.yaw,
+            pitch: self.pitch,
+            speed: Some(self.speed),
+            sensitivity: Some(self.sensitivity),
+            selected_block: Some(self.selected_block),
+        }
+    }
+
+    pub fn restore_state(&mut self, state: &PlayerState) {
+        self.position = Vec3::from(state.position);
+        self.camera_pos = Vec3::new(self.position.x, self.position.y, self.position.z + self.camera_offset);
+        self.chunk_pos = ChunkManager::get_chunk_at(state.position);
+        self.yaw = state.yaw;
+        self.pitch = state.pitch;
+        if let Some(speed) = state.speed {
+            self.speed = speed;
+        }
+        if let Some(sensitivity) = state.sensitivity {
+            self.sensitivity = sensitivity;
+        }
+        if let Some(block) = state.selected_block {
+            self.selected_block = block;
+        }
+    }
+
+    // number keys pick from the hotbar, call on key press only
+    pub fn handle_key_pressed(&mut self, key: PhysicalKey) {
+        let slot = match key {
+            PhysicalKey::Code(KeyCode::Digit1) => 0,
+            PhysicalKey::Code(KeyCode::Digit2) => 1,
+            PhysicalKey::Code(KeyCode::Digit3) => 2,
+            PhysicalKey::Code(KeyCode::Digit4) => 3,
+            PhysicalKey::Code(KeyCode::Digit5) => 4,
+            PhysicalKey::Code(KeyCode::Digit6) => 5,
+            PhysicalKey::Code(KeyCode::Digit7) => 6,
+            PhysicalKey::Code(KeyCode::Digit8) => 7,
+            PhysicalKey::Code(KeyCode::Digit9) => 8,
+            _ => return,
+        };
+        self.selected_block = HOTBAR[slot];
+        println!("Selected {:?}", self.selected_block);
     }
 
     pub fn handle_keyboard_inputs(&mut self, keys_pressed: &HashSet<PhysicalKey>, window_target: &ActiveEventLoop, delta_time: f32) {
@@ -95,6 +160,80 @@ impl Player
             
 
 
+    }
+
+    // the block under the crosshair, if one is within reach
+    pub fn target_block(&self, chunks: &HashMap<[i32; 3], Arc<Chunk>>) -> Option<RaycastHit> {
+        raycast(self.camera_pos, self.camera_front, MAX_REACH, chunks)
+    }
+
+    // sets the targeted block to air, bedrock can't be broken
+    pub fn break_block(&self, chunk_manager: &ChunkManager) -> bool {
+        let hit = self.target_block(&chunk_manager.chunks.read().unwrap());
+        match hit {
+            Some(hit) if hit.block_type != BlockType::Bedrock => chunk_manager.set_block(hit.block_pos, BlockType::Air),
+            _ => false,
+        }
+    }
+
+    // puts the selected block against the targeted face, as long as that cell is free and not where we stand
+    pub fn place_block(&self, chunk_manager: &ChunkManager) -> bool {
+        let chunks = chunk_manager.chunks.read().unwrap();
+        let Some(hit) = self.target_block(&chunks) else {
+            return false;
+        };
+        let place_pos = hit.place_pos();
+        let free = ChunkManager::get_block(place_pos, &chunks)
+            .is_some_and(|block| block.block_type == BlockType::Air || block.block_type == BlockType::Water || block.block_type.is_plant());
+        drop(chunks);
+
+        let own_cell = [self.camera_pos.x, self.camera_pos.y, self.camera_pos.z].map(|coord| (coord + 0.5).floor() as i32);
+        if !free || place_pos == own_cell {
+            return false;
+        }
+        chunk_manager.set_block(place_pos, self.selected_block)
+    }
+
+    // sets one corner of the selection to the targeted block
+    pub fn mark_corner(&mut self, corner: usize, chunk_manager: &ChunkManager) {
+        let Some(hit) = self.target_block(&chunk_manager.chunks.read().unwrap()) else {
+            return;
+        };
+        self.selection[corner] = Some(hit.block_pos);
+        println!("Corner {} at {:?}", corner + 1, hit.block_pos);
+    }
+
+    // saves the selected box to `path`
+    pub fn copy_selection(&self, chunk_manager: &ChunkManager, path: &Path) {
+        let [Some(min), Some(max)] = self.selection else {
+            println!("Mark both corners before copying");
+            return;
+        };
+        let schematic = Schematic::from_world(min, max, &chunk_manager.chunks.read().unwrap());
+        match schematic.save(path) {
+            Ok(()) => println!("Copied {:?} blocks to {}", schematic.size, path.display()),
+            Err(e) => println!("Failed to save schematic: {}", e),
+        }
+    }
+
+    // pastes the schematic at `path` with its lowest corner against the targeted face. unknown
+    // blocks become stone and air in the schematic leaves the world alone
+    pub fn paste_schematic(&self, chunk_manager: &ChunkManager, path: &Path) {
+        let Some(hit) = self.target_block(&chunk_manager.chunks.read().unwrap()) else {
+            return;
+        };
+        let (schematic, unknown) = match Schematic::load(path, BlockType::Stone) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                println!("Failed to load schematic {}: {}", path.display(), e);
+                return;
+            }
+        };
+        if !unknown.is_empty() {
+            println!("Unmapped blocks (placed as stone): {}", unknown.join(", "));
+        }
+        let count = schematic.paste(hit.place_pos(), chunk_manager, true);
+        println!("Pasted {} blocks at {:?}", count, hit.place_pos());
     }
 
     pub fn get_view_matrix(&mut self) -> [[f32; 4]; 4] {
@@ -0,0 +1,172 @@
+use std::{collections::HashMap, sync::Arc};
+
+use nalgebra_glm::Vec3;
+
+use crate::{block::BlockType, chunk::Chunk, chunk_manager::ChunkManager};
+
+// how far the player can reach, in blocks
+pub const MAX_REACH: f32 = 6.0;
+
+/// The first targetable block along a ray.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RaycastHit {
+    pub block_pos: [i32; 3],
+    pub block_type: BlockType,
+    // unit normal of the face the ray entered through, zero if the ray started inside the block
+    pub normal: [i32; 3],
+    pub distance: f32,
+}
+
+impl RaycastHit {
+    // the empty cell in front of the hit face, where a placed block goes
+    pub fn place_pos(&self) -> [i32; 3] {
+        [
+            self.block_pos[0] + self.normal[0],
+            self.block_pos[1] + self.normal[1],
+            self.block_pos[2] + self.normal[2],
+        ]
+    }
+}
+
+// air and water can't be targeted, you aim through them
+fn is_targetable(block_type: BlockType) -> bool {
+    block_type != BlockType::Air && block_type != BlockType::Water
+}
+
+// walks the voxel grid cell by cell (Amanatides & Woo) until it finds a targetable block or runs out of reach.
+// unloaded chunks count as empty
+pub fn raycast(origin: Vec3, direction: Vec3, max_distance: f32, chunks: &HashMap<[i32; 3], Arc<Chunk>>) -> Option<RaycastHit> {
+    if direction.norm() == 0.0 {
+        return None;
+    }
+    let direction = direction.normalize();
+    // blocks are centered on integer coords, shift so cell boundaries land on integers
+    let start = [origin.x + 0.5, origin.y + 0.5, origin.z + 0.5];
+    let dir = [direction.x, direction.y, direction.z];
+
+    let mut cell = start.map(|coord| coord.floor() as i32);
+    let mut step = [0; 3];
+    // distance along the ray to the next boundary on each axis, and between boundaries
+    let mut t_max = [f32::INFINITY; 3];
+    let mut t_delta = [f32::INFINITY; 3];
+    for axis in 0..3 {
+        if dir[axis] > 0.0 {
+            step[axis] = 1;
+            t_max[axis] = (cell[axis] as f32 + 1.0 - start[axis]) / dir[axis];
+            t_delta[axis] = 1.0 / dir[axis];
+        } else if dir[axis] < 0.0 {
+            step[axis] = -1;
+            t_max[axis] = (cell[axis] as f32 - start[axis]) / dir[axis];
+            t_delta[axis] = -1.0 / dir[axis];
+        }
+    }
+
+    let mut normal = [0; 3];
+    let mut distance = 0.0;
+    while distance <= max_distance {
+        if let Some(block) = ChunkManager::get_block(cell, chunks) {
+            if is_targetable(block.block_type) {
+                return Some(RaycastHit { block_pos: cell, block_type: block.block_type, normal, distance });
+            }
+        }
+
+        let axis = if t_max[0] < t_max[1] {
+            if t_max[0] < t_max[2] { 0 } else { 2 }
+        } else if t_max[1] < t_max[2] { 1 } else { 2 };
+
+        distance = t_max[axis];
+        cell[axis] += step[axis];
+        t_max[axis] += t_delta[axis];
+        normal = [0; 3];
+        normal[axis] = -step[axis];
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a loaded map of empty chunks around the origin with the given blocks set
+    fn world(blocks: &[([i32; 3], BlockType)]) -> HashMap<[i32; 3], Arc<Chunk>> {
+        let mut chunks = HashMap::new();
+        for x in -2..=1 {
+            for y in -2..=1 {
+                for z in -2..=1 {
+                    chunks.insert([x, y, z], Arc::new(Chunk::empty([x, y, z])));
+                }
+            }
+        }
+        for (pos, block_type) in blocks {
+            let origin = pos.map(|coord| coord.div_euclid(16));
+            let [x, y, z] = pos.map(|coord| coord.rem_euclid(16) as usize);
+            Arc::make_mut(chunks.get_mut(&origin).unwrap()).set(x, y, z, *block_type);
+        }
+        chunks
+    }
+
+    #[test]
+    fn hits_along_each_axis() {
+        let cases = [
+            (Vec3::new(1.0, 0.0, 0.0), [3, 0, 0], [-1, 0, 0]),
+            (Vec3::new(-1.0, 0.0, 0.0), [-3, 0, 0], [1, 0, 0]),
+            (Vec3::new(0.0, 1.0, 0.0), [0, 3, 0], [0, -1, 0]),
+            (Vec3::new(0.0, -1.0, 0.0), [0, -3, 0], [0, 1, 0]),
+            (Vec3::new(0.0, 0.0, 1.0), [0, 0, 3], [0, 0, -1]),
+            (Vec3::new(0.0, 0.0, -1.0), [0, 0, -3], [0, 0, 1]),
+        ];
+        for (direction, block_pos, normal) in cases {
+            let chunks = world(&[(block_pos, BlockType::Stone)]);
+            let hit = raycast(Vec3::zeros(), direction, MAX_REACH, &chunks).unwrap();
+            assert_eq!(hit.block_pos, block_pos);
+            assert_eq!(hit.block_type, BlockType::Stone);
+            assert_eq!(hit.normal, normal);
+            // blocks are centered on integers, so the near face is half a block closer
+            assert!((hit.distance - 2.5).abs() < 1e-5);
+            let expected_place = [block_pos[0] + normal[0], block_pos[1] + normal[1], block_pos[2] + normal[2]];
+            assert_eq!(hit.place_pos(), expected_place);
+        }
+    }
+
+    #[test]
+    fn diagonal_ray_enters_through_the_crossed_face() {
+        let chunks = world(&[([2, 2, 0], BlockType::Dirt)]);
+        // slightly steeper than 45 degrees, so it reaches y = 2 first and comes in from the -x side
+        let hit = raycast(Vec3::zeros(), Vec3::new(1.0, 1.1, 0.0), MAX_REACH, &chunks).unwrap();
+        assert_eq!(hit.block_pos, [2, 2, 0]);
+        assert_eq!(hit.normal, [-1, 0, 0]);
+        assert_eq!(hit.place_pos(), [1, 2, 0]);
+        assert!(hit.distance > 2.0 && hit.distance < 3.0);
+    }
+
+    #[test]
+    fn works_across_chunk_borders_at_negative_coordinates() {
+        let chunks = world(&[([-17, -20, -16], BlockType::Log)]);
+        let origin = Vec3::new(-14.0, -20.0, -16.0);
+        let hit = raycast(origin, Vec3::new(-1.0, 0.0, 0.0), MAX_REACH, &chunks).unwrap();
+        assert_eq!(hit.block_pos, [-17, -20, -16]);
+        assert_eq!(hit.normal, [1, 0, 0]);
+        assert_eq!(hit.place_pos(), [-16, -20, -16]);
+    }
+
+    #[test]
+    fn skips_air_and_water_and_stops_at_reach() {
+        let chunks = world(&[([0, 0, 2], BlockType::Water), ([0, 0, 4], BlockType::Sand)]);
+        let hit = raycast(Vec3::zeros(), Vec3::new(0.0, 0.0, 1.0), MAX_REACH, &chunks).unwrap();
+        assert_eq!(hit.block_pos, [0, 0, 4]);
+
+        assert!(raycast(Vec3::zeros(), Vec3::new(0.0, 0.0, 1.0), 3.0, &chunks).is_none());
+        let far = world(&[([0, 10, 0], BlockType::Stone)]);
+        assert!(raycast(Vec3::zeros(), Vec3::new(0.0, 1.0, 0.0), MAX_REACH, &far).is_none());
+        assert!(raycast(Vec3::zeros(), Vec3::zeros(), MAX_REACH, &chunks).is_none());
+    }
+
+    #[test]
+    fn starting_inside_a_block_hits_it_with_no_normal() {
+        let chunks = world(&[([0, 0, 0], BlockType::Stone)]);
+        let hit = raycast(Vec3::new(0.2, 0.1, -0.3), Vec3::new(1.0, 0.0, 0.0), MAX_REACH, &chunks).unwrap();
+        assert_eq!(hit.block_pos, [0, 0, 0]);
+        assert_eq!(hit.normal, [0, 0, 0]);
+        assert_eq!(hit.distance, 0.0);
+    }
+}
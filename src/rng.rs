@@ -0,0 +1,41 @@
+use crate::terrain::WorldSeed;
+
+/// Small deterministic PRNG (SplitMix64) for decoration passes.
+/// Seeded from the world seed and a chunk origin so a regenerated chunk gets the exact same features.
+pub struct ChunkRng {
+    state: u64,
+}
+
+impl ChunkRng {
+    // `salt` keeps separate passes (ores, trees, ...) from drawing the same numbers
+    pub fn new(seed: WorldSeed, origin: [i32; 3], salt: u64) -> Self {
+        let mut rng = ChunkRng { state: seed.0 as u64 ^ salt.wrapping_mul(0x9e3779b97f4a7c15) };
+        for coord in origin {
+            rng.state ^= coord as u32 as u64;
+            rng.next_u64();
+        }
+        rng
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    // uniform in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // uniform in [min, max), max must be greater than min
+    pub fn range(&mut self, min: i32, max: i32) -> i32 {
+        min + (self.next_u64() % (max - min) as u64) as i32
+    }
+
+    pub fn chance(&mut self, probability: f64) -> bool {
+        self.next_f64() < probability
+    }
+}
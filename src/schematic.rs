@@ -0,0 +1,305 @@
+use std::{collections::HashMap, fs, io::{self, Read, Write}, path::Path, sync::Arc};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+use crate::{anvil::{block_from_name, block_name}, block::BlockType, chunk::Chunk, chunk_manager::ChunkManager, nbt::{self, Tag}, world_save::write_atomic};
+
+// written into exported files, the block names we use are all valid for 1.20
+const DATA_VERSION: i32 = 3465;
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("schematic: {}", msg.into()))
+}
+
+/// A box of blocks that can be copied out of one world and pasted into another, stored as a
+/// Sponge `.schem` file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schematic {
+    // width (x), height (y), length (z)
+    pub size: [usize; 3],
+    // x fastest, then z, then y, the same order as the file
+    pub blocks: Vec<BlockType>,
+}
+
+impl Schematic {
+    fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        x + z * self.size[0] + y * self.size[0] * self.size[2]
+    }
+
+    pub fn get(&self, x: usize, y: usize, z: usize) -> BlockType {
+        self.blocks[self.index(x, y, z)]
+    }
+
+    // copies every block between the two corners (inclusive), anything in an unloaded chunk is air
+    pub fn from_world(min: [i32; 3], max: [i32; 3], chunks: &HashMap<[i32; 3], Arc<Chunk>>) -> Schematic {
+        let low: [i32; 3] = std::array::from_fn(|axis| min[axis].min(max[axis]));
+        let high: [i32; 3] = std::array::from_fn(|axis| min[axis].max(max[axis]));
+        let size = std::array::from_fn(|axis| (high[axis] - low[axis] + 1) as usize);
+
+        let mut blocks = Vec::with_capacity(size[0] * size[1] * size[2]);
+        for y in low[1]..=high[1] {
+            for z in low[2]..=high[2] {
+                for x in low[0]..=high[0] {
+                    let block = ChunkManager::get_block([x, y, z], chunks);
+                    blocks.push(block.map_or(BlockType::Air, |block| block.block_type));
+                }
+            }
+        }
+        Schematic { size, blocks }
+    }
+
+    // writes the blocks with their lowest corner at `at` into whatever chunks are loaded and
+    // remeshes them. air is skipped when `skip_air` is set so the paste doesn't carve out the
+    // surroundings. returns how many blocks were set
+    pub fn paste(&self, at: [i32; 3], chunk_manager: &ChunkManager, skip_air: bool) -> usize {
+        let mut edits = Vec::new();
+        for y in 0..self.size[1] {
+            for z in 0..self.size[2] {
+                for x in 0..self.size[0] {
+                    let block_type = self.get(x, y, z);
+                    if skip_air && block_type == BlockType::Air {
+                        continue;
+                    }
+                    edits.push(([at[0] + x as i32, at[1] + y as i32, at[2] + z as i32], block_type));
+                }
+            }
+        }
+        chunk_manager.set_blocks(edits)
+    }
+
+    // gzipped Sponge schematic, version 2
+    pub fn to_schem_bytes(&self) -> io::Result<Vec<u8>> {
+        let dimension = |axis: usize| -> io::Result<Tag> {
+            u16::try_from(self.size[axis]).map(|len| Tag::Short(len as i16)).map_err(|_| invalid("more than 65535 blocks along an axis"))
+        };
+
+        let mut palette: HashMap<BlockType, i32> = HashMap::new();
+        let mut block_data = Vec::new();
+        for block_type in &self.blocks {
+            let next_id = palette.len() as i32;
+            let id = *palette.entry(*block_type).or_insert(next_id);
+            // unsigned LEB128 varints
+            let mut value = id as u32;
+            loop {
+                let byte = (value & 0x7f) as u8;
+                value >>= 7;
+                if value == 0 {
+                    block_data.push(byte as i8);
+                    break;
+                }
+                block_data.push((byte | 0x80) as i8);
+            }
+        }
+
+        let palette_tag = palette.iter().map(|(block_type, id)| (block_name(*block_type).to_string(), Tag::Int(*id))).collect();
+        let root = Tag::Compound(HashMap::from([
+            ("Version".to_string(), Tag::Int(2)),
+            ("DataVersion".to_string(), Tag::Int(DATA_VERSION)),
+            ("Width".to_string(), dimension(0)?),
+            ("Height".to_string(), dimension(1)?),
+            ("Length".to_string(), dimension(2)?),
+            ("Offset".to_string(), Tag::IntArray(vec![0, 0, 0])),
+            ("PaletteMax".to_string(), Tag::Int(palette.len() as i32)),
+            ("Palette".to_string(), Tag::Compound(palette_tag)),
+            ("BlockData".to_string(), Tag::ByteArray(block_data)),
+        ]));
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&nbt::write("Schematic", &root))?;
+        encoder.finish()
+    }
+
+    // reads version 2 and 3 files, gzipped or not. blocks we have no type for become `placeholder`,
+    // their names are returned alongside
+    pub fn from_schem_bytes(bytes: &[u8], placeholder: BlockType) -> io::Result<(Schematic, Vec<String>)> {
+        let mut nbt_bytes = Vec::new();
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            GzDecoder::new(bytes).read_to_end(&mut nbt_bytes)?;
+        } else {
+            nbt_bytes.extend_from_slice(bytes);
+        }
+        let (_, root) = nbt::read(&nbt_bytes)?;
+        // version 3 wraps everything in another "Schematic" compound and moves the blocks down a level
+        let root = root.get("Schematic").unwrap_or(&root);
+        let blocks_tag = root.get("Blocks");
+        let palette = blocks_tag.and_then(|blocks| blocks.get("Palette")).or_else(|| root.get("Palette"));
+        let data = blocks_tag.and_then(|blocks| blocks.get("Data")).or_else(|| root.get("BlockData"));
+
+        let dimension = |key: &str| -> io::Result<usize> {
+            // stored as signed shorts but meant as unsigned
+            root.get(key).and_then(Tag::as_i64).map(|len| len as u16 as usize).ok_or_else(|| invalid(format!("missing {}", key)))
+        };
+        let size = [dimension("Width")?, dimension("Height")?, dimension("Length")?];
+        let volume = size[0] * size[1] * size[2];
+
+        let Some(Tag::Compound(palette)) = palette else {
+            return Err(invalid("missing palette"));
+        };
+        let mut unknown = Vec::new();
+        let mut by_id: HashMap<u32, BlockType> = HashMap::new();
+        for (name, id) in palette {
+            let id = id.as_i64().and_then(|id| u32::try_from(id).ok()).ok_or_else(|| invalid(format!("bad palette id for {}", name)))?;
+            let block_type = block_from_name(name).unwrap_or_else(|| {
+                unknown.push(name.clone());
+                placeholder
+            });
+            by_id.insert(id, block_type);
+        }
+
+        let Some(Tag::ByteArray(data)) = data else {
+            return Err(invalid("missing block data"));
+        };
+        // every block takes at least a byte, checked before trusting the size for an allocation
+        if data.len() < volume {
+            return Err(invalid("block data ends early"));
+        }
+        let mut blocks = Vec::with_capacity(volume);
+        let mut bytes = data.iter().map(|byte| *byte as u8);
+        while blocks.len() < volume {
+            let mut id: u32 = 0;
+            let mut shift = 0;
+            loop {
+                let byte = bytes.next().ok_or_else(|| invalid("block data ends early"))?;
+                if shift >= 32 {
+                    return Err(invalid("varint too long"));
+                }
+                id |= ((byte & 0x7f) as u32) << shift;
+                shift += 7;
+                if byte & 0x80 == 0 {
+                    break;
+                }
+            }
+            blocks.push(by_id.get(&id).copied().unwrap_or(placeholder));
+        }
+
+        unknown.sort();
+        Ok((Schematic { size, blocks }, unknown))
+    }
+
+    // writes a .schem file, creating its directory if needed
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        write_atomic(path, &self.to_schem_bytes()?)
+    }
+
+    pub fn load(path: &Path, placeholder: BlockType) -> io::Result<(Schematic, Vec<String>)> {
+        Schematic::from_schem_bytes(&fs::read(path)?, placeholder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, sync::mpsc};
+
+    use strum::IntoEnumIterator;
+
+    use crate::{chunk_manager::BufferTask, test_util::TempDir};
+
+    use super::*;
+
+    // every block type, repeated to fill an uneven box
+    fn sample() -> Schematic {
+        let size = [5, 3, 7];
+        let types: Vec<BlockType> = BlockType::iter().collect();
+        let blocks = (0..size[0] * size[1] * size[2]).map(|i| types[(i * 7) % types.len()]).collect();
+        Schematic { size, blocks }
+    }
+
+    #[test]
+    fn schem_bytes_round_trip() {
+        let schematic = sample();
+        let bytes = schematic.to_schem_bytes().unwrap();
+        assert!(bytes.starts_with(&[0x1f, 0x8b]));
+
+        let (read, unknown) = Schematic::from_schem_bytes(&bytes, BlockType::Stone).unwrap();
+        assert_eq!(read, schematic);
+        assert!(unknown.is_empty());
+    }
+
+    #[test]
+    fn reads_uncompressed_version_3_with_unknown_blocks() {
+        let compound = |fields: Vec<(&str, Tag)>| Tag::Compound(fields.into_iter().map(|(key, tag)| (key.to_string(), tag)).collect());
+        let blocks = compound(vec![
+            ("Palette", compound(vec![("minecraft:sand", Tag::Int(0)), ("minecraft:amethyst_block", Tag::Int(1))])),
+            ("Data", Tag::ByteArray(vec![0, 1, 1, 0])),
+        ]);
+        let root = compound(vec![("Schematic", compound(vec![
+            ("Version", Tag::Int(3)),
+            ("Width", Tag::Short(2)),
+            ("Height", Tag::Short(1)),
+            ("Length", Tag::Short(2)),
+            ("Blocks", blocks),
+        ]))]);
+
+        let (read, unknown) = Schematic::from_schem_bytes(&nbt::write("", &root), BlockType::Dirt).unwrap();
+        assert_eq!(read.size, [2, 1, 2]);
+        assert_eq!(read.blocks, vec![BlockType::Sand, BlockType::Dirt, BlockType::Dirt, BlockType::Sand]);
+        assert_eq!(unknown, vec!["minecraft:amethyst_block".to_string()]);
+    }
+
+    #[test]
+    fn copy_then_paste_reproduces_the_blocks() {
+        let origins = [[0, 0, 0], [1, 0, 0], [0, 0, -1], [1, 0, -1]];
+        let chunks: HashMap<[i32; 3], Arc<Chunk>> = origins.iter()
+            .map(|origin| (*origin, Arc::new(Chunk::from_fn(*origin, |x, y, z| if (x + y + z) % 3 == 0 { BlockType::Log } else { BlockType::Air }))))
+            .collect();
+
+        // straddles the chunk border at x 16 and z 0, corners given in the wrong order on purpose
+        let copied = Schematic::from_world([20, 4, 3], [12, 1, -3], &chunks);
+        assert_eq!(copied.size, [9, 4, 7]);
+        for (x, y, z) in [(0, 0, 0), (8, 3, 6), (4, 2, 3), (3, 1, 2)] {
+            let world = [12 + x as i32, 1 + y as i32, -3 + z as i32];
+            let expected = ChunkManager::get_block(world, &chunks).unwrap().block_type;
+            assert_eq!(copied.get(x, y, z), expected);
+        }
+        // anything outside loaded chunks reads as air
+        assert!(Schematic::from_world([40, 0, 0], [41, 1, 1], &chunks).blocks.iter().all(|block| *block == BlockType::Air));
+    }
+
+    #[test]
+    fn paste_sets_blocks_and_marks_chunks_modified_and_dirty() {
+        let (task_sender, _tasks) = mpsc::channel();
+        let (buffer_sender, buffer_tasks) = mpsc::channel();
+        let manager = ChunkManager::new(task_sender, buffer_sender);
+        for origin in [[0, 0, 0], [1, 0, 0]] {
+            manager.chunks.write().unwrap().insert(origin, Arc::new(Chunk::empty(origin)));
+        }
+
+        // three blocks along x across the chunk border, the middle one air
+        let schematic = Schematic { size: [3, 1, 1], blocks: vec![BlockType::Stone, BlockType::Air, BlockType::Log] };
+        assert_eq!(schematic.paste([15, 8, 8], &manager, true), 2);
+
+        let chunks = manager.chunks.read().unwrap();
+        assert_eq!(chunks[&[0, 0, 0]].get(15, 8, 8), BlockType::Stone);
+        assert_eq!(chunks[&[1, 0, 0]].get(1, 8, 8), BlockType::Log);
+        drop(chunks);
+        assert_eq!(*manager.modified.lock().unwrap(), HashSet::from([[0, 0, 0], [1, 0, 0]]));
+
+        let Ok(BufferTask::UpdateBuffers(dirty)) = buffer_tasks.try_recv() else {
+            panic!("paste didn't ask for a remesh");
+        };
+        assert!(dirty.contains(&[0, 0, 0]) && dirty.contains(&[1, 0, 0]));
+        // one remesh for the whole paste
+        assert!(buffer_tasks.try_recv().is_err());
+
+        // without skip_air the air overwrites too
+        manager.chunks.write().unwrap().insert([1, 0, 0], Arc::new(Chunk::from_fn([1, 0, 0], |_, _, _| BlockType::Dirt)));
+        assert_eq!(schematic.paste([15, 8, 8], &manager, false), 3);
+        assert_eq!(manager.chunks.read().unwrap()[&[1, 0, 0]].get(0, 8, 8), BlockType::Air);
+
+        // nothing lands in chunks that aren't loaded
+        assert_eq!(schematic.paste([100, 8, 8], &manager, false), 0);
+    }
+
+    #[test]
+    fn save_and_load_file() {
+        let temp = TempDir::new("schematic_file");
+        let path = temp.0.join("nested").join("sample.schem");
+        let schematic = sample();
+        schematic.save(&path).unwrap();
+        let (read, _) = Schematic::load(&path, BlockType::Stone).unwrap();
+        assert_eq!(read, schematic);
+    }
+}
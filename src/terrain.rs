@@ -0,0 +1,291 @@
+use std::{array, collections::HashMap, sync::{Arc, RwLock}};
+
+use crate::{biome::{BiomeMap, ColumnSample}, block::BlockType, caves::{CaveCarver, CaveConfig, ColumnHeights}, chunk::Chunk, decoration::{PendingEdit, VegetationPass}, ores::OrePass};
+
+/// Seed used for all terrain noise. The same seed always produces the same world.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WorldSeed(pub u32);
+
+impl WorldSeed {
+    // numbers are used as-is, anything else gets hashed so "my world" works as a seed too
+    pub fn from_arg(arg: &str) -> Self {
+        if let Ok(seed) = arg.trim().parse::<u32>() {
+            return WorldSeed(seed);
+        }
+
+        // FNV-1a, std's hasher isn't guaranteed to be stable between releases
+        let mut hash: u32 = 0x811c9dc5;
+        for byte in arg.bytes() {
+            hash ^= byte as u32;
+            hash = hash.wrapping_mul(0x01000193);
+        }
+        WorldSeed(hash)
+    }
+
+    // seed from the first command line argument, falls back to 0
+    pub fn from_env() -> Self {
+        std::env::args()
+            .nth(1)
+            .map(|arg| WorldSeed::from_arg(&arg))
+            .unwrap_or_default()
+    }
+}
+
+/// Anything that can fill in a chunk. Called from the chunk worker pool, so it has to be shareable.
+pub trait TerrainGenerator: Send + Sync {
+    fn generate(&self, origin: [i32; 3]) -> Chunk;
+
+    // features (trees, plants) placed after `generate`. returns the blocks that land in other chunks
+    fn decorate(&self, _chunk: &mut Chunk) -> Vec<PendingEdit> {
+        Vec::new()
+    }
+
+    // called when the player moves so generators can drop cached data for far away chunks
+    fn unload_outside(&self, _center: [i32; 3], _render_distance: i32) {}
+
+    // y of the topmost generated block (water included) in a column, None if the column is empty.
+    // ignores decoration, used to find somewhere to spawn
+    fn surface_height(&self, _x: i32, _z: i32) -> Option<i32> {
+        None
+    }
+}
+
+// picks a generator by name, used for the second command line argument
+pub fn generator_from_name(name: &str, seed: WorldSeed) -> Option<Arc<dyn TerrainGenerator>> {
+    match name {
+        "default" | "heightmap" => Some(Arc::new(HeightmapGenerator::new(seed))),
+        "flat" | "superflat" => Some(Arc::new(SuperflatGenerator::default())),
+        "void" => Some(Arc::new(VoidGenerator)),
+        _ => None,
+    }
+}
+
+pub type ColumnData = [[ColumnSample; 16]; 16];
+
+/// Heights and biomes of whole 16x16 columns keyed by (x, z) chunk coordinates, shared by all
+/// worker threads so the vertical chunks of a column only sample the noise once.
+#[derive(Default)]
+pub struct ColumnCache {
+    columns: RwLock<HashMap<[i32; 2], Arc<ColumnData>>>,
+}
+
+impl ColumnCache {
+    pub fn get_or_insert_with(&self, key: [i32; 2], compute: impl FnOnce() -> ColumnData) -> Arc<ColumnData> {
+        if let Some(column) = self.columns.read().unwrap().get(&key) {
+            return column.clone();
+        }
+
+        // computed without holding the lock, two threads may race on the same column but
+        // they produce identical data so whichever lands first wins
+        let column = Arc::new(compute());
+        self.columns.write().unwrap().entry(key).or_insert(column).clone()
+    }
+
+    // drops columns further than `radius` chunks (on x or z) from `center`
+    pub fn retain_in_range(&self, center: [i32; 2], radius: i32) {
+        self.columns.write().unwrap().retain(|key, _| {
+            (key[0] - center[0]).abs() <= radius && (key[1] - center[1]).abs() <= radius
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.columns.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The default Perlin/FBM heightmap terrain.
+pub struct HeightmapGenerator {
+    pub seed: WorldSeed,
+    // how many blocks of the biome's filler (dirt, sand) sit between the surface and the stone
+    pub filler_depth: i32,
+    // y of the bedrock floor, nothing is generated below it
+    pub bedrock_y: i32,
+    // open air below this y is filled with water
+    pub sea_level: i32,
+    // None turns caves off
+    pub caves: Option<CaveCarver>,
+    pub biomes: BiomeMap,
+    // None turns ores off
+    pub ores: Option<OrePass>,
+    // None turns trees and plants off
+    pub vegetation: Option<VegetationPass>,
+    // None samples the noise again for every chunk in a column
+    pub column_cache: Option<ColumnCache>,
+}
+
+impl HeightmapGenerator {
+    pub fn new(seed: WorldSeed) -> Self {
+        HeightmapGenerator {
+            seed,
+            filler_depth: 3,
+            bedrock_y: -48,
+            sea_level: 22,
+            caves: Some(CaveCarver::new(seed, CaveConfig::default())),
+            biomes: BiomeMap::new(seed),
+            ores: Some(OrePass::new(seed)),
+            vegetation: Some(VegetationPass::new(seed)),
+            column_cache: Some(ColumnCache::default()),
+        }
+    }
+
+    fn sample_columns(&self, chunk_x: i32, chunk_z: i32) -> ColumnData {
+        array::from_fn(|x| 
+            array::from_fn(|z| {
+                self.biomes.sample_column(chunk_x * 16 + x as i32, chunk_z * 16 + z as i32)
+            })
+        )
+    }
+
+    // heights and biomes for every x, z in the chunk column
+    pub fn columns(&self, chunk_x: i32, chunk_z: i32) -> Arc<ColumnData> {
+        match &self.column_cache {
+            Some(cache) => cache.get_or_insert_with([chunk_x, chunk_z], || self.sample_columns(chunk_x, chunk_z)),
+            None => Arc::new(self.sample_columns(chunk_x, chunk_z)),
+        }
+    }
+
+    // heights of the chunk column and the one column border around it, for the cave carver
+    fn column_heights(&self, chunk_x: i32, chunk_z: i32) -> ColumnHeights {
+        let neighbours: [[Arc<ColumnData>; 3]; 3] = array::from_fn(|dx| array::from_fn(|dz| self.columns(chunk_x + dx as i32 - 1, chunk_z + dz as i32 - 1)));
+        array::from_fn(|bx| array::from_fn(|bz| {
+            // bx and bz run from -1 to 16 relative to the chunk, shifted up by one
+            let (x, z) = (bx + 15, bz + 15);
+            neighbours[x / 16][z / 16][x % 16][z % 16].height
+        }))
+    }
+
+    // block type for a given world y in a column
+    fn layer_at(&self, world_y: i32, column: &ColumnSample) -> BlockType {
+        let height = column.height;
+        if world_y > height && world_y < self.sea_level {
+            BlockType::Water
+        } else if world_y < self.bedrock_y || world_y > height {
+            BlockType::Air
+        } else if world_y == self.bedrock_y {
+            BlockType::Bedrock
+        } else if world_y == height {
+            column.biome.params().surface
+        } else if world_y >= height - self.filler_depth {
+            column.biome.params().filler
+        } else {
+            BlockType::Stone
+        }
+    }
+}
+
+impl TerrainGenerator for HeightmapGenerator {
+    fn generate(&self, origin: [i32; 3]) -> Chunk {
+        let chunk_size = 16;
+        let world_origin = [origin[0] * chunk_size, origin[1] * chunk_size, origin[2] * chunk_size ];
+        //println!("New chunk at {}, {}, {}", world_origin[0], world_origin[1], world_origin[2]);
+        // heights and biomes for all x,z coordinates, shared with the rest of the column
+        let columns = self.columns(origin[0], origin[2]);
+
+        let mut chunk = Chunk::from_fn(origin, |x, y, z| self.layer_at(world_origin[1] + y as i32, &columns[x][z]));
+
+        if let Some(caves) = &self.caves {
+            caves.carve(&mut chunk, &self.column_heights(origin[0], origin[2]), self.sea_level);
+        }
+
+        // ores go in after the caves, veins only replace stone that survived carving
+        if let Some(ores) = &self.ores {
+            ores.decorate(&mut chunk);
+        }
+
+        // carving can empty a chunk completely, drop back to the smallest storage
+        chunk.compact();
+
+        chunk
+    }
+
+    fn decorate(&self, chunk: &mut Chunk) -> Vec<PendingEdit> {
+        match &self.vegetation {
+            Some(vegetation) => vegetation.decorate(chunk),
+            None => Vec::new(),
+        }
+    }
+
+    fn unload_outside(&self, center: [i32; 3], render_distance: i32) {
+        if let Some(cache) = &self.column_cache {
+            cache.retain_in_range([center[0], center[2]], render_distance);
+        }
+    }
+
+    fn surface_height(&self, x: i32, z: i32) -> Option<i32> {
+        let height = self.biomes.sample_column(x, z).height;
+        Some(height.max(self.sea_level - 1))
+    }
+}
+
+/// Flat layers stacked up from y = 0, handy for building and test fixtures.
+pub struct SuperflatGenerator {
+    // bottom to top, layers[0] is at y = 0
+    pub layers: Vec<BlockType>,
+}
+
+impl Default for SuperflatGenerator {
+    fn default() -> Self {
+        SuperflatGenerator { layers: vec![BlockType::Bedrock, BlockType::Stone, BlockType::Dirt, BlockType::Dirt, BlockType::Grass] }
+    }
+}
+
+impl TerrainGenerator for SuperflatGenerator {
+    fn generate(&self, origin: [i32; 3]) -> Chunk {
+        let base_y = origin[1] * 16;
+        Chunk::from_fn(origin, |_, y, _| {
+            let world_y = base_y + y as i32;
+            if world_y < 0 {
+                return BlockType::Air;
+            }
+            self.layers.get(world_y as usize).copied().unwrap_or(BlockType::Air)
+        })
+    }
+
+    fn surface_height(&self, _x: i32, _z: i32) -> Option<i32> {
+        self.layers.iter().rposition(|layer| *layer != BlockType::Air).map(|top| top as i32)
+    }
+}
+
+/// Nothing but air.
+pub struct VoidGenerator;
+
+impl TerrainGenerator for VoidGenerator {
+    fn generate(&self, origin: [i32; 3]) -> Chunk {
+        Chunk::empty(origin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGINS: [[i32; 3]; 6] = [[0, 0, 0], [0, 1, 0], [-1, 0, 2], [3, -2, -5], [0, -1, 0], [-7, 1, 4]];
+
+    #[test]
+    fn same_seed_generates_same_blocks() {
+        let first = HeightmapGenerator::new(WorldSeed(1234));
+        let second = HeightmapGenerator::new(WorldSeed(1234));
+
+        // the second one goes backwards so its column cache is filled in a different order
+        let from_first: Vec<_> = ORIGINS.iter().map(|origin| first.generate(*origin)).collect();
+        let mut from_second: Vec<_> = ORIGINS.iter().rev().map(|origin| second.generate(*origin)).collect();
+        from_second.reverse();
+
+        for (mut a, mut b) in from_first.into_iter().zip(from_second) {
+            assert_eq!(a.to_bytes(), b.to_bytes(), "chunk {:?}", a.origin);
+            assert_eq!(first.decorate(&mut a), second.decorate(&mut b));
+            assert_eq!(a.to_bytes(), b.to_bytes(), "decorated chunk {:?}", a.origin);
+        }
+    }
+
+    #[test]
+    fn different_seeds_generate_different_terrain() {
+        let a = HeightmapGenerator::new(WorldSeed(1));
+        let b = HeightmapGenerator::new(WorldSeed(2));
+        assert!(ORIGINS.iter().any(|origin| a.generate(*origin).to_bytes() != b.generate(*origin).to_bytes()));
+    }
+}
@@ -0,0 +1,20 @@
+// helpers shared by the unit tests
+
+use std::{fs, path::PathBuf};
+
+/// A fresh directory per test, removed again when dropped, even if the test panics.
+pub struct TempDir(pub PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> TempDir {
+        let dir = std::env::temp_dir().join(format!("snake_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        TempDir(dir)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
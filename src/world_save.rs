@@ -0,0 +1,366 @@
+use std::{collections::{BTreeMap, HashMap}, fs, io::{self, Read, Seek, SeekFrom, Write}, path::{Path, PathBuf}};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+
+use crate::{block::BlockType, chunk::Chunk};
+
+// chunks per region file along each axis
+pub const REGION_SIZE: i32 = 16;
+const REGION_VOLUME: usize = (REGION_SIZE * REGION_SIZE * REGION_SIZE) as usize;
+const REGION_MAGIC: &[u8; 4] = b"SRGN";
+const REGION_VERSION: u32 = 1;
+// magic, version, then one (offset, length) pair of u32s per chunk slot
+const HEADER_LEN: usize = 8 + REGION_VOLUME * 8;
+
+// writes to a temporary file next to `path` and renames it over the original, so a crash or kill
+// halfway through leaves the previous file untouched
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+
+    let mut file = fs::File::create(&temp_path)?;
+    file.write_all(bytes)?;
+    // the data has to be on disk before the rename makes it the real file
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&temp_path, path)
+}
+
+/// Where the player was, how they were looking and their settings when the world was saved.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlayerState {
+    pub position: [f32; 3],
+    pub yaw: f32,
+    pub pitch: f32,
+    // the rest can be missing from older level files, the player keeps its defaults then
+    pub speed: Option<f32>,
+    pub sensitivity: Option<f32>,
+    pub selected_block: Option<BlockType>,
+}
+
+/// Everything about a world that isn't chunks, stored in the `level` file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LevelData {
+    pub seed: u32,
+    pub generator: String,
+    pub spawn: [f32; 3],
+    pub player: Option<PlayerState>,
+}
+
+impl LevelData {
+    // one `key values...` line per field, unknown keys are ignored when reading
+    pub fn to_text(&self) -> String {
+        let mut text = format!("seed {}\ngenerator {}\n", self.seed, self.generator);
+        text += &format!("spawn {} {} {}\n", self.spawn[0], self.spawn[1], self.spawn[2]);
+        if let Some(player) = &self.player {
+            text += &format!("player_position {} {} {}\n", player.position[0], player.position[1], player.position[2]);
+            text += &format!("player_yaw {}\nplayer_pitch {}\n", player.yaw, player.pitch);
+            if let Some(speed) = player.speed {
+                text += &format!("player_speed {}\n", speed);
+            }
+            if let Some(sensitivity) = player.sensitivity {
+                text += &format!("player_sensitivity {}\n", sensitivity);
+            }
+            if let Some(block) = player.selected_block {
+                text += &format!("player_selected_block {}\n", block.id());
+            }
+        }
+        text
+    }
+
+    pub fn from_text(text: &str) -> Option<LevelData> {
+        let fields: HashMap<&str, &str> = text.lines()
+            .filter_map(|line| line.trim().split_once(' '))
+            .collect();
+
+        let floats = |key: &str| -> Option<Vec<f32>> {
+            fields.get(key)?.split_whitespace().map(|value| value.parse().ok()).collect()
+        };
+        let vec3 = |key: &str| -> Option<[f32; 3]> { floats(key)?.try_into().ok() };
+        let float = |key: &str| -> Option<f32> { fields.get(key)?.trim().parse().ok() };
+
+        let player = match (vec3("player_position"), float("player_yaw"), float("player_pitch")) {
+            (Some(position), Some(yaw), Some(pitch)) => Some(PlayerState {
+                position,
+                yaw,
+                pitch,
+                speed: float("player_speed"),
+                sensitivity: float("player_sensitivity"),
+                selected_block: fields.get("player_selected_block")
+                    .and_then(|id| id.trim().parse().ok())
+                    .and_then(BlockType::from_id),
+            }),
+            _ => None,
+        };
+
+        Some(LevelData {
+            seed: fields.get("seed")?.trim().parse().ok()?,
+            generator: fields.get("generator").map_or("default", |name| name.trim()).to_string(),
+            spawn: vec3("spawn")?,
+            player,
+        })
+    }
+}
+
+/// A world directory: a `level` file plus region files under `region/`, each holding up to
+/// REGION_SIZE³ zlib compressed chunks behind an offset table.
+pub struct WorldSave {
+    dir: PathBuf,
+}
+
+impl WorldSave {
+    // creates the directory layout if it isn't there yet
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<WorldSave> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(dir.join("region"))?;
+        Ok(WorldSave { dir })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn level_path(&self) -> PathBuf {
+        self.dir.join("level")
+    }
+
+    pub fn region_of(origin: [i32; 3]) -> [i32; 3] {
+        origin.map(|coord| coord.div_euclid(REGION_SIZE))
+    }
+
+    fn region_path(&self, region: [i32; 3]) -> PathBuf {
+        self.dir.join("region").join(format!("r.{}.{}.{}.region", region[0], region[1], region[2]))
+    }
+
+    // slot of a chunk within its region's offset table
+    fn slot_of(origin: [i32; 3]) -> usize {
+        let [x, y, z] = origin.map(|coord| coord.rem_euclid(REGION_SIZE) as usize);
+        let size = REGION_SIZE as usize;
+        x * size * size + y * size + z
+    }
+
+    // None if the world has never been saved
+    pub fn load_level(&self) -> io::Result<Option<LevelData>> {
+        match fs::read_to_string(self.level_path()) {
+            Ok(text) => LevelData::from_text(&text)
+                .map(Some)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed level file")),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save_level(&self, level: &LevelData) -> io::Result<()> {
+        write_atomic(&self.level_path(), level.to_text().as_bytes())
+    }
+
+    // compressed chunk blobs in a region file, by slot. empty if the file doesn't exist
+    fn read_region(&self, region: [i32; 3]) -> io::Result<BTreeMap<usize, Vec<u8>>> {
+        let bytes = match fs::read(self.region_path(region)) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+            Err(e) => return Err(e),
+        };
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", self.region_path(region).display(), msg));
+
+        if bytes.len() < HEADER_LEN || &bytes[0..4] != REGION_MAGIC {
+            return Err(invalid("not a region file"));
+        }
+        if u32::from_le_bytes(bytes[4..8].try_into().unwrap()) != REGION_VERSION {
+            return Err(invalid("unsupported region version"));
+        }
+
+        let mut blobs = BTreeMap::new();
+        for slot in 0..REGION_VOLUME {
+            let entry = 8 + slot * 8;
+            let offset = u32::from_le_bytes(bytes[entry..entry + 4].try_into().unwrap()) as usize;
+            let length = u32::from_le_bytes(bytes[entry + 4..entry + 8].try_into().unwrap()) as usize;
+            if offset == 0 {
+                continue;
+            }
+            let blob = bytes.get(offset..offset + length).ok_or_else(|| invalid("chunk runs past end of file"))?;
+            blobs.insert(slot, blob.to_vec());
+        }
+        Ok(blobs)
+    }
+
+    fn write_region(&self, region: [i32; 3], blobs: &BTreeMap<usize, Vec<u8>>) -> io::Result<()> {
+        let mut header = vec![0u8; HEADER_LEN];
+        header[0..4].copy_from_slice(REGION_MAGIC);
+        header[4..8].copy_from_slice(&REGION_VERSION.to_le_bytes());
+
+        let mut data = Vec::new();
+        for (slot, blob) in blobs {
+            let entry = 8 + slot * 8;
+            let offset = (HEADER_LEN + data.len()) as u32;
+            header[entry..entry + 4].copy_from_slice(&offset.to_le_bytes());
+            header[entry + 4..entry + 8].copy_from_slice(&(blob.len() as u32).to_le_bytes());
+            data.extend_from_slice(blob);
+        }
+
+        header.extend_from_slice(&data);
+        write_atomic(&self.region_path(region), &header)
+    }
+
+    // one chunk's compressed blob, reading only its table entry and data. files are replaced by
+    // rename, so an open handle always sees one complete version
+    fn read_slot(&self, region: [i32; 3], slot: usize) -> io::Result<Option<Vec<u8>>> {
+        let path = self.region_path(region);
+        let mut file = match fs::File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), msg));
+
+        let mut start = [0u8; 8];
+        file.read_exact(&mut start)?;
+        if &start[0..4] != REGION_MAGIC {
+            return Err(invalid("not a region file"));
+        }
+        if u32::from_le_bytes(start[4..8].try_into().unwrap()) != REGION_VERSION {
+            return Err(invalid("unsupported region version"));
+        }
+
+        let mut entry = [0u8; 8];
+        file.seek(SeekFrom::Start(8 + slot as u64 * 8))?;
+        file.read_exact(&mut entry)?;
+        let offset = u32::from_le_bytes(entry[0..4].try_into().unwrap()) as u64;
+        let length = u32::from_le_bytes(entry[4..8].try_into().unwrap()) as usize;
+        if offset == 0 {
+            return Ok(None);
+        }
+        if offset + length as u64 > file.metadata()?.len() {
+            return Err(invalid("chunk runs past end of file"));
+        }
+
+        let mut blob = vec![0; length];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut blob)?;
+        Ok(Some(blob))
+    }
+
+    pub fn load_chunk(&self, origin: [i32; 3]) -> io::Result<Option<Chunk>> {
+        let Some(blob) = self.read_slot(WorldSave::region_of(origin), WorldSave::slot_of(origin))? else {
+            return Ok(None);
+        };
+
+        let mut bytes = Vec::new();
+        ZlibDecoder::new(blob.as_slice()).read_to_end(&mut bytes)?;
+        Chunk::from_bytes(&bytes)
+            .filter(|chunk| chunk.origin == origin)
+            .map(Some)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("corrupt chunk at {:?}", origin)))
+    }
+
+    // writes the chunks into their region files, each touched file is rewritten once
+    pub fn save_chunks<'a>(&self, chunks: impl IntoIterator<Item = &'a Chunk>) -> io::Result<()> {
+        let mut by_region: HashMap<[i32; 3], Vec<&Chunk>> = HashMap::new();
+        for chunk in chunks {
+            by_region.entry(WorldSave::region_of(chunk.origin)).or_default().push(chunk);
+        }
+
+        for (region, chunks) in by_region {
+            let mut blobs = self.read_region(region)?;
+            for chunk in chunks {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(&chunk.to_bytes())?;
+                blobs.insert(WorldSave::slot_of(chunk.origin), encoder.finish()?);
+            }
+            self.write_region(region, &blobs)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::TempDir;
+
+    use super::*;
+
+    fn chunk(origin: [i32; 3]) -> Chunk {
+        Chunk::from_fn(origin, |x, y, z| match (x + y * 3 + z * 7 + origin[0].unsigned_abs() as usize) % 5 {
+            0 => BlockType::Stone,
+            1 => BlockType::Dirt,
+            2 => BlockType::Log,
+            _ => BlockType::Air,
+        })
+    }
+
+    #[test]
+    fn chunks_round_trip_across_regions() {
+        let temp = TempDir::new("chunks_round_trip");
+        let save = WorldSave::open(&temp.0).unwrap();
+        let origins = [[0, 0, 0], [15, 2, 15], [16, 0, 0], [-1, -1, -1], [-17, 3, 40]];
+        let chunks: Vec<Chunk> = origins.iter().map(|origin| chunk(*origin)).collect();
+        save.save_chunks(&chunks).unwrap();
+
+        // a second save into an existing region keeps what was already there
+        let extra = chunk([1, 0, 0]);
+        save.save_chunks([&extra]).unwrap();
+
+        for chunk in chunks.iter().chain([&extra]) {
+            let loaded = save.load_chunk(chunk.origin).unwrap().expect("saved chunk is missing");
+            assert_eq!(loaded.origin, chunk.origin);
+            assert_eq!(loaded.to_bytes(), chunk.to_bytes());
+        }
+
+        // an empty slot in an existing region, and a region that was never written
+        assert!(save.load_chunk([2, 0, 0]).unwrap().is_none());
+        assert!(save.load_chunk([100, 100, 100]).unwrap().is_none());
+    }
+
+    #[test]
+    fn level_round_trip() {
+        let temp = TempDir::new("level_round_trip");
+        let save = WorldSave::open(&temp.0).unwrap();
+        assert_eq!(save.load_level().unwrap(), None);
+
+        let level = LevelData {
+            seed: 1234,
+            generator: "superflat".to_string(),
+            spawn: [0.5, 70.0, -3.25],
+            player: Some(PlayerState {
+                position: [10.0, 65.5, -20.0],
+                yaw: 1.5,
+                pitch: -0.25,
+                speed: Some(7.0),
+                sensitivity: None,
+                selected_block: Some(BlockType::Log),
+            }),
+        };
+        save.save_level(&level).unwrap();
+        assert_eq!(save.load_level().unwrap(), Some(level));
+    }
+    #[test]
+    fn interrupted_write_leaves_previous_files_loadable() {
+        let temp = TempDir::new("interrupted_write");
+        let save = WorldSave::open(&temp.0).unwrap();
+        let saved = chunk([3, 1, 2]);
+        save.save_chunks([&saved]).unwrap();
+        let level = LevelData { seed: 7, generator: "default".to_string(), spawn: [0.0, 40.0, 0.0], player: None };
+        save.save_level(&level).unwrap();
+
+        // what a crash halfway through write_atomic leaves behind: half a temp file next to the real one
+        let region_path = save.region_path(WorldSave::region_of(saved.origin));
+        let region_bytes = fs::read(&region_path).unwrap();
+        let mut temp_name = region_path.file_name().unwrap().to_os_string();
+        temp_name.push(".tmp");
+        fs::write(region_path.with_file_name(temp_name), &region_bytes[..HEADER_LEN / 2]).unwrap();
+        fs::write(temp.0.join("level.tmp"), "seed 99
+gener").unwrap();
+
+        assert_eq!(save.load_chunk(saved.origin).unwrap().unwrap().to_bytes(), saved.to_bytes());
+        assert_eq!(save.load_level().unwrap(), Some(level.clone()));
+
+        // and the next save writes over the leftover instead of tripping on it
+        let other = chunk([4, 1, 2]);
+        save.save_chunks([&other]).unwrap();
+        assert_eq!(save.load_chunk(saved.origin).unwrap().unwrap().to_bytes(), saved.to_bytes());
+        assert_eq!(save.load_chunk(other.origin).unwrap().unwrap().to_bytes(), other.to_bytes());
+        save.save_level(&level).unwrap();
+        assert!(!temp.0.join("level.tmp").exists());
+    }
+}